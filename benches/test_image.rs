@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use bke_ccl::*;
 use criterion::{criterion_group, criterion_main, Criterion};
 use pollster::FutureExt;
@@ -6,7 +8,9 @@ use pollster::FutureExt;
 use criterion::async_executor::FuturesExecutor;
 
 pub struct WGPUState {
+    pipelines: Arc<CCLPipelines>,
     texture_bundle: texture::TextureUInt,
+    ccl: CCLState,
     device: wgpu::Device,
     queue: wgpu::Queue,
 }
@@ -29,9 +33,13 @@ impl WGPUState {
 
         let texture_bundle =
             texture::TextureUInt::from_bytes(&device, &queue, image_bytes, "in_texture").unwrap();
+        let pipelines = Arc::new(CCLPipelines::new(&device));
+        let ccl = CCLState::with_pipelines(&device, &queue, pipelines.clone(), &texture_bundle).unwrap();
 
         Ok(Self {
+            pipelines,
             texture_bundle,
+            ccl,
             device,
             queue,
         })
@@ -43,34 +51,34 @@ impl WGPUState {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-        let ccl = CCLState::new(&self.device, &self.queue, &self.texture_bundle).unwrap();
-        let _output_buffer = ccl.compute(&mut encoder)?;
+        let _output_buffer = self.ccl.compute(&mut encoder)?;
+        self.queue.submit(std::iter::once(encoder.finish()));
 
         Ok(())
     }
-}
 
-async fn create_wgpu_state() -> Result<(), Box<dyn std::error::Error>> {
-    let image_bytes = include_bytes!("test_8connectivity_maxlabelEE.png");
-    let _state = WGPUState::new(image_bytes).await?;
-    Ok(())
-}
+    /// Creates a new state on top of the shared pipelines and computes the labels with it,
+    /// the cost of labeling an image that was not seen before.
+    async fn compute_with_new_state(&self) -> anyhow::Result<()> {
+        let ccl = CCLState::with_pipelines(&self.device, &self.queue, self.pipelines.clone(), &self.texture_bundle)?;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        let _output_buffer = ccl.compute(&mut encoder)?;
+        self.queue.submit(std::iter::once(encoder.finish()));
 
-async fn compute_wgpu_state() -> Result<(), Box<dyn std::error::Error>> {
-    let image_bytes = include_bytes!("test_8connectivity_maxlabelEE.png");
-    let state = WGPUState::new(image_bytes).await?;
-    state.compute().await?;
-    Ok(())
+        Ok(())
+    }
 }
 
-async fn create_wgpu_state_finger() -> Result<(), Box<dyn std::error::Error>> {
-    let image_bytes = include_bytes!("fingerprint.png");
+async fn create_wgpu_state(image_bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
     let _state = WGPUState::new(image_bytes).await?;
     Ok(())
 }
 
-async fn compute_wgpu_state_finger() -> Result<(), Box<dyn std::error::Error>> {
-    let image_bytes = include_bytes!("fingerprint.png");
+async fn compute_wgpu_state(image_bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
     let state = WGPUState::new(image_bytes).await?;
     state.compute().await?;
     Ok(())
@@ -79,15 +87,15 @@ async fn compute_wgpu_state_finger() -> Result<(), Box<dyn std::error::Error>> {
 fn simple_image(c: &mut Criterion) {
     let mut group = c.benchmark_group("WGPU Benchmarks");
 
-    group.bench_function("test only setup", |b| {
-        b.to_async(FuturesExecutor).iter(|| create_wgpu_state()); // Benchmarks WGPUState::new()
+    let image_bytes = include_bytes!("test_8connectivity_maxlabelEE.png");
+    group.bench_function("test full setup", |b| {
+        b.to_async(FuturesExecutor).iter(|| create_wgpu_state(image_bytes)); // Benchmarks the device, the upload, the pipelines and the state
     });
 
-    group.bench_function("test with setup", |b| {
-        b.to_async(FuturesExecutor).iter(|| compute_wgpu_state()); // Benchmarks state.compute()
+    group.bench_function("test full setup and compute", |b| {
+        b.to_async(FuturesExecutor).iter(|| compute_wgpu_state(image_bytes)); // Benchmarks the device, the upload, the pipelines, the state and compute()
     });
 
-    let image_bytes = include_bytes!("test_8connectivity_maxlabelEE.png");
     let state = WGPUState::new(image_bytes).block_on().expect("could not create state");
     group.bench_function("test only compute", |b| {
         b.to_async(FuturesExecutor).iter(|| {
@@ -95,15 +103,21 @@ fn simple_image(c: &mut Criterion) {
         });
     });
 
-    group.bench_function("finger only setup", |b| {
-        b.to_async(FuturesExecutor).iter(|| create_wgpu_state_finger()); // Benchmarks WGPUState::new()
+    group.bench_function("test new state with shared pipelines", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            state.compute_with_new_state() // Measure CCLState::with_pipelines() and compute()
+        });
+    });
+
+    let image_bytes = include_bytes!("fingerprint.png");
+    group.bench_function("finger full setup", |b| {
+        b.to_async(FuturesExecutor).iter(|| create_wgpu_state(image_bytes)); // Benchmarks the device, the upload, the pipelines and the state
     });
 
-    group.bench_function("finger with setup", |b| {
-        b.to_async(FuturesExecutor).iter(|| compute_wgpu_state_finger()); // Benchmarks state.compute()
+    group.bench_function("finger full setup and compute", |b| {
+        b.to_async(FuturesExecutor).iter(|| compute_wgpu_state(image_bytes)); // Benchmarks the device, the upload, the pipelines, the state and compute()
     });
 
-    let image_bytes = include_bytes!("fingerprint.png");
     let state = WGPUState::new(image_bytes).block_on().expect("could not create state");
     group.bench_function("finger only compute", |b| {
         b.to_async(FuturesExecutor).iter(|| {
@@ -111,6 +125,12 @@ fn simple_image(c: &mut Criterion) {
         });
     });
 
+    group.bench_function("finger new state with shared pipelines", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            state.compute_with_new_state() // Measure CCLState::with_pipelines() and compute()
+        });
+    });
+
     let image_bytes = include_bytes!("tobacco.png");
     let state = WGPUState::new(image_bytes).block_on().expect("could not create state");
     group.bench_function("tobacco only compute", |b| {
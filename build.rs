@@ -1,3 +1,13 @@
+/// The features of `input.wesl` for every input format but Rgba8Uint, with the suffix of
+/// their artifacts.
+const INPUT_VARIANTS: [(&str, &str); 5] = [
+    ("input_r8", "r8"),
+    ("input_uint", "uint"),
+    ("input_float", "float"),
+    ("input_bytes", "bytes"),
+    ("input_bits", "bits"),
+];
+
 fn main() {
     wesl::Wesl::new("src/shaders").build_artifact(&"package::render".parse().unwrap(), "render");
     wesl::Wesl::new("src/shaders").build_artifact(&"package::init_labeling".parse().unwrap(), "init_labeling");
@@ -5,4 +15,28 @@ fn main() {
     wesl::Wesl::new("src/shaders").build_artifact(&"package::merge".parse().unwrap(), "merge");
     wesl::Wesl::new("src/shaders").build_artifact(&"package::final_labeling".parse().unwrap(), "final_labeling");
     wesl::Wesl::new("src/shaders").build_artifact(&"package::label_to_rgba".parse().unwrap(), "label_to_rgba");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::scan".parse().unwrap(), "scan");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::relabel".parse().unwrap(), "relabel");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::count".parse().unwrap(), "count");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::stats".parse().unwrap(), "stats");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::moments".parse().unwrap(), "moments");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::classes".parse().unwrap(), "classes");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::colorize".parse().unwrap(), "colorize");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::filter".parse().unwrap(), "filter");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::holes".parse().unwrap(), "holes");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::boundary".parse().unwrap(), "boundary");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::volume".parse().unwrap(), "volume");
+    wesl::Wesl::new("src/shaders").build_artifact(&"package::volume_merge".parse().unwrap(), "volume_merge");
+
+    for (feature, suffix) in INPUT_VARIANTS {
+        for shader in ["init_labeling", "classes"] {
+            wesl::Wesl::new("src/shaders")
+                .set_feature(feature, true)
+                .build_artifact(&format!("package::{shader}").parse().unwrap(), &format!("{shader}_{suffix}"));
+        }
+    }
+    // the layers of a batch, see `src/batch.rs`
+    wesl::Wesl::new("src/shaders")
+        .set_feature("input_uint_array", true)
+        .build_artifact(&"package::init_labeling".parse().unwrap(), "init_labeling_uint_array");
 }
@@ -0,0 +1,86 @@
+use crate::{BindGroups, CCLPipelines, Dimensions, Foreground, Input, PixelValue, create_storage_buffers, relabel::Relabel};
+
+/// Which pixels get component ids.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Phase {
+    /// Only the foreground pixels.
+    #[default]
+    Foreground,
+    /// Only the background pixels, the foreground test is inverted.
+    Background,
+    /// Both, in one run. The background is labeled into buffers of its own, so its labels
+    /// are a separate range, see [`crate::CCLState::read_background_labels`].
+    Both,
+}
+
+/// The buffers and bind groups of the background phase of [`Phase::Both`] and
+/// [`crate::CCLOptions::fill_holes`]. It runs the same passes as the foreground with the
+/// inverted foreground test, the optional stages besides `relabel` only run for the
+/// foreground. The background has a dimensions uniform of its own, as its connectivity
+/// can differ.
+pub(crate) struct Background {
+    dims_buffer: wgpu::Buffer,
+    foreground_buffer: wgpu::Buffer,
+    pub(crate) labels_buffer: wgpu::Buffer,
+    info_buffer: wgpu::Buffer,
+    pub(crate) count_buffer: wgpu::Buffer,
+    pub(crate) bind_groups: BindGroups,
+    pub(crate) relabel: Option<Relabel>,
+    /// Number of pixels the labels and info buffers can hold.
+    capacity: u64,
+}
+
+impl Background {
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue, pipelines: &CCLPipelines, input: &Input, capacity: u64) -> Background {
+        let dims_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Background Dimensions Uniform"),
+            size: std::mem::size_of::<Dimensions>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let foreground_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Background Foreground Uniform"),
+            size: std::mem::size_of::<crate::foreground::ForegroundParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (labels_buffer, info_buffer) = create_storage_buffers(device, queue, capacity);
+        let count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Background Count Buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_groups = BindGroups::new(device, pipelines, input, &labels_buffer, &info_buffer, &dims_buffer, &foreground_buffer, &count_buffer);
+
+        Self {
+            dims_buffer,
+            foreground_buffer,
+            labels_buffer,
+            info_buffer,
+            count_buffer,
+            bind_groups,
+            relabel: None,
+            capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub(crate) fn write_dims(&self, queue: &wgpu::Queue, dims: Dimensions) {
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[dims]));
+    }
+
+    /// Writes the inverse of the foreground test of the foreground phase.
+    pub(crate) fn write_foreground(&self, queue: &wgpu::Queue, foreground: Foreground, multi_value: Option<PixelValue>, channels: u32) {
+        let params = foreground.inverted().to_params(multi_value).with_channels(channels);
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Rebinds the phase to a new input of at most `capacity` pixels.
+    pub(crate) fn set_input(&mut self, device: &wgpu::Device, pipelines: &CCLPipelines, input: &Input) {
+        self.bind_groups = BindGroups::new(device, pipelines, input, &self.labels_buffer, &self.info_buffer, &self.dims_buffer, &self.foreground_buffer, &self.count_buffer);
+    }
+}
@@ -0,0 +1,302 @@
+//! Labeling of many equally sized images in one submission, e.g. the tiles of a large
+//! image.
+//!
+//! The images are the layers of a 2D array texture and the z dimension of every dispatch
+//! indexes the layer. The batch runs the passes of [`crate::CCLState`], with the labels
+//! of all layers in one buffer, layer after layer. The union-find never connects pixels
+//! of different layers, and [`BatchState::read_labels`] counts the labels of every layer
+//! from its own first pixel, like the labels of a [`crate::CCLState`] without relabeling.
+
+use std::sync::Arc;
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{BindGroups, CCLPipelines, Connectivity, Dimensions, Foreground, InitPass, LabelMap, readback};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchOptions {
+    pub connectivity: Connectivity,
+    /// The test of the pixels that are labeled. Multi-value labeling is not supported for
+    /// batches.
+    pub foreground: Foreground,
+}
+
+/// Uploads equally sized images as the layers of an Rgba8Uint 2D array texture, to label
+/// with [`BatchState`]. The number of images is limited by
+/// [`wgpu::Limits::max_texture_array_layers`] of the device.
+pub fn upload_batch(device: &wgpu::Device, queue: &wgpu::Queue, images: &[image::DynamicImage]) -> anyhow::Result<wgpu::Texture> {
+    let Some(first) = images.first() else {
+        anyhow::bail!("a batch needs at least one image");
+    };
+    let (width, height) = (first.width(), first.height());
+    anyhow::ensure!(
+        images.iter().all(|img| (img.width(), img.height()) == (width, height)),
+        "the images of a batch have different dimensions"
+    );
+    let max_layers = device.limits().max_texture_array_layers;
+    anyhow::ensure!(images.len() as u64 <= max_layers as u64, "a batch holds at most {max_layers} images, not {}", images.len());
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: images.len() as u32,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Batch Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    for (layer, img) in images.iter().enumerate() {
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+            },
+            &img.to_rgba8(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d { depth_or_array_layers: 1, ..size },
+        );
+    }
+    Ok(texture)
+}
+
+/// The buffers and bind groups to label one batch of images in a single pass.
+pub struct BatchState {
+    pipelines: Arc<CCLPipelines>,
+    width: u32,
+    height: u32,
+    layers: u32,
+    /// Number of pixels of all layers the labels and info buffers can hold.
+    capacity: u64,
+    /// Number of layers the count buffer can hold.
+    layer_capacity: u32,
+    options: BatchOptions,
+    // the number of channels of the texels
+    channels: u32,
+    dims_buffer: wgpu::Buffer,
+    foreground_buffer: wgpu::Buffer,
+    labels_buffer: wgpu::Buffer,
+    info_buffer: wgpu::Buffer,
+    counts_buffer: wgpu::Buffer,
+    bind_groups: BindGroups,
+}
+
+impl BatchState {
+    /// Creates the pipelines and the state for one batch. Prefer
+    /// [`BatchState::with_pipelines`] when more than one batch is labeled.
+    pub fn new(device: &wgpu::Device, images: &wgpu::Texture) -> anyhow::Result<BatchState> {
+        let pipelines = Arc::new(CCLPipelines::new(device));
+        Self::with_pipelines(device, pipelines, images)
+    }
+
+    /// Creates the state for a 2D texture with one image per array layer, with unsigned
+    /// integer texels and texture binding usage, e.g. one of [`upload_batch`].
+    /// Single-channel texels read as grey like those of an [`crate::Input`]. The pipelines can be shared with [`crate::CCLState`]s.
+    pub fn with_pipelines(device: &wgpu::Device, pipelines: Arc<CCLPipelines>, images: &wgpu::Texture) -> anyhow::Result<BatchState> {
+        check_batch(images)?;
+        let size = images.size();
+        let options = BatchOptions::default();
+        let dims_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Batch Dimensions Uniform"),
+            contents: bytemuck::cast_slice(&[dims(size, options.connectivity)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let foreground_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Batch Foreground Uniform"),
+            contents: bytemuck::cast_slice(&[options.foreground.to_params(None).with_channels(images.format().components() as u32)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let capacity = size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64;
+        let (labels_buffer, info_buffer) = create_storage_buffers(device, capacity);
+        let layer_capacity = size.depth_or_array_layers;
+        let counts_buffer = create_counts_buffer(device, layer_capacity);
+        let bind_groups = create_bind_groups(device, &pipelines, images, &labels_buffer, &info_buffer, &dims_buffer, &foreground_buffer, &counts_buffer);
+
+        Ok(Self {
+            pipelines,
+            width: size.width,
+            height: size.height,
+            layers: size.depth_or_array_layers,
+            capacity,
+            layer_capacity,
+            options,
+            channels: images.format().components() as u32,
+            dims_buffer,
+            foreground_buffer,
+            labels_buffer,
+            info_buffer,
+            counts_buffer,
+            bind_groups,
+        })
+    }
+
+    /// Binds a new batch to this state. The buffers are only reallocated if the new batch
+    /// has more pixels or layers than they can hold.
+    pub fn set_input(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, images: &wgpu::Texture) -> anyhow::Result<()> {
+        check_batch(images)?;
+        let size = images.size();
+        (self.width, self.height, self.layers) = (size.width, size.height, size.depth_or_array_layers);
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[dims(size, self.options.connectivity)]));
+        self.channels = images.format().components() as u32;
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[self.options.foreground.to_params(None).with_channels(self.channels)]));
+
+        let num_pixels = self.num_pixels();
+        if num_pixels > self.capacity {
+            self.capacity = num_pixels.next_power_of_two();
+            (self.labels_buffer, self.info_buffer) = create_storage_buffers(device, self.capacity);
+        }
+        if self.layers > self.layer_capacity {
+            self.layer_capacity = self.layers.next_power_of_two();
+            self.counts_buffer = create_counts_buffer(device, self.layer_capacity);
+        }
+        self.bind_groups = create_bind_groups(device, &self.pipelines, images, &self.labels_buffer, &self.info_buffer, &self.dims_buffer, &self.foreground_buffer, &self.counts_buffer);
+        Ok(())
+    }
+
+    pub fn options(&self) -> BatchOptions {
+        self.options
+    }
+
+    /// Sets the connectivity and the foreground test for the following calls to
+    /// [`BatchState::compute`].
+    pub fn set_options(&mut self, queue: &wgpu::Queue, options: BatchOptions) {
+        self.options = options;
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.layers,
+        };
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[dims(size, options.connectivity)]));
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[options.foreground.to_params(None).with_channels(self.channels)]));
+    }
+
+    /// Width and height of every image of the bound batch.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Number of images of the bound batch.
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    fn num_pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.layers as u64
+    }
+
+    /// Records all passes into `encoder`. Returns the labels buffer, one u32 per pixel
+    /// layer after layer, and the component counts buffer, one u32 per layer. The labels
+    /// of layer `l` are offset by `l * width * height`, see [`BatchState::read_labels`].
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) -> (wgpu::Buffer, wgpu::Buffer) {
+        // 8x8 threads with 2x2 pixels each
+        let blocks = (self.width.div_ceil(16), self.height.div_ceil(16));
+        encoder.clear_buffer(&self.counts_buffer, 0, Some(self.layers as u64 * 4));
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Batch Compute Pass"),
+                timestamp_writes: None,
+            });
+            self.bind_groups.dispatch_labeling(&mut compute_pass, &self.pipelines, blocks, self.layers);
+        }
+        (self.labels_buffer.clone(), self.counts_buffer.clone())
+    }
+
+    /// Reads the labels of every image of the bound batch back to the CPU, counted from
+    /// the first pixel of its layer. The work recorded by [`BatchState::compute`] has to
+    /// be submitted before.
+    pub async fn read_labels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<LabelMap>> {
+        let labels = readback::read_buffer::<u32>(device, queue, &self.labels_buffer, self.num_pixels()).await?;
+        let layer_len = self.width as usize * self.height as usize;
+        Ok(labels
+            .chunks_exact(layer_len.max(1))
+            .take(self.layers as usize)
+            .zip(0..)
+            .map(|(layer, offset)| {
+                // the roots of a layer lie in it, so every label is larger than the offset
+                let labels = layer.iter().map(|&label| if label == 0 { 0 } else { label - offset * layer_len as u32 }).collect();
+                LabelMap::new(self.width, self.height, labels)
+            })
+            .collect())
+    }
+
+    /// Reads the number of components of every image of the bound batch back to the CPU.
+    pub async fn read_component_counts(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        readback::read_buffer::<u32>(device, queue, &self.counts_buffer, self.layers as u64).await
+    }
+}
+
+fn check_batch(images: &wgpu::Texture) -> anyhow::Result<()> {
+    anyhow::ensure!(images.dimension() == wgpu::TextureDimension::D2, "a batch has to be a 2D (array) texture");
+    anyhow::ensure!(
+        images.format().sample_type(None, None) == Some(wgpu::TextureSampleType::Uint),
+        "the batch has to have unsigned integer texels, not {:?}",
+        images.format()
+    );
+    anyhow::ensure!(images.usage().contains(wgpu::TextureUsages::TEXTURE_BINDING), "the batch needs texture binding usage");
+    Ok(())
+}
+
+/// The dimensions of every layer, the passes are dispatched once per layer.
+fn dims(size: wgpu::Extent3d, connectivity: Connectivity) -> Dimensions {
+    Dimensions {
+        columns: size.width,
+        rows: size.height,
+        connectivity: connectivity.as_u32(),
+        pixel_nodes: (connectivity == Connectivity::Four) as u32,
+    }
+}
+
+/// Creates the labels and info buffers for `capacity` pixels of all layers.
+fn create_storage_buffers(device: &wgpu::Device, capacity: u64) -> (wgpu::Buffer, wgpu::Buffer) {
+    // one u32 per pixel
+    let size = capacity.checked_mul(4).expect("The batch was too big to create a storage buffer");
+    let create_buffer = |label| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    };
+    (create_buffer("Batch Labels Buffer"), create_buffer("Batch Info Buffer"))
+}
+
+fn create_counts_buffer(device: &wgpu::Device, layers: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Batch Component Counts Buffer"),
+        size: layers.max(1) as u64 * 4,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_bind_groups(
+    device: &wgpu::Device,
+    pipelines: &CCLPipelines,
+    images: &wgpu::Texture,
+    labels_buffer: &wgpu::Buffer,
+    info_buffer: &wgpu::Buffer,
+    dims_buffer: &wgpu::Buffer,
+    foreground_buffer: &wgpu::Buffer,
+    counts_buffer: &wgpu::Buffer,
+) -> BindGroups {
+    // a texture with a single layer defaults to a D2 view
+    let view = images.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    let input = wgpu::BindingResource::TextureView(&view);
+    BindGroups::with_init_pass(device, pipelines, InitPass::Batch, input, labels_buffer, info_buffer, dims_buffer, foreground_buffer, counts_buffer)
+}
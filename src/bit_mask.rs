@@ -0,0 +1,78 @@
+use crate::{input::unpack_bits, readback};
+
+/// A storage buffer with one bit per pixel of the bound image, in the layout of
+/// [`crate::InputFormat::Bits`]. The shaders set the bits with `atomicOr`, so the mask has
+/// to be cleared before every dispatch.
+pub(crate) struct BitMask {
+    buffer: wgpu::Buffer,
+    /// Number of pixels the mask can hold.
+    capacity: u64,
+    /// Number of pixels of the bound image.
+    len: u64,
+}
+
+impl BitMask {
+    pub(crate) fn new(device: &wgpu::Device, label: &str, capacity: u64) -> BitMask {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: capacity.div_ceil(32).max(1) * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            capacity,
+            len: capacity,
+        }
+    }
+
+    /// The read-write storage binding of a mask in a bind group layout.
+    pub(crate) fn layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    pub(crate) fn entry(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: self.buffer.as_entire_binding(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// The number of pixels of the bound image.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Sets the number of pixels of the bound image.
+    pub(crate) fn set_len(&mut self, num_pixels: u64) {
+        self.len = num_pixels.min(self.capacity);
+    }
+
+    pub(crate) fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Clears the bits of the bound image. Has to be recorded outside of a compute pass.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(&self.buffer, 0, Some(self.len.div_ceil(32).max(1) * 4));
+    }
+
+    /// Reads the bits of the bound image back, one bool per pixel.
+    pub(crate) async fn read_bits(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<bool>> {
+        let words = readback::read_buffer::<u32>(device, queue, &self.buffer, self.len.div_ceil(32)).await?;
+        Ok(unpack_bits(&words, self.len as usize))
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::OnceLock;
+
+use crate::input::{InitPipelines, InputFormat};
+
+pub(crate) struct ClassesPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// One pipeline for every input format, indexed by [`InputFormat::index`].
+    store_pipelines: [OnceLock<wgpu::ComputePipeline>; InputFormat::ALL.len()],
+}
+
+impl ClassesPipelines {
+    pub(crate) fn new(device: &wgpu::Device) -> ClassesPipelines {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("classes_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        Self {
+            bind_group_layout,
+            store_pipelines: [const { OnceLock::new() }; InputFormat::ALL.len()],
+        }
+    }
+
+    /// The pass for inputs of `format`, compiled on first use. The init bind group of the
+    /// input format is bound to group 0, the classes are read from the input.
+    pub(crate) fn store_pipeline(&self, device: &wgpu::Device, init: &InitPipelines, format: InputFormat) -> &wgpu::ComputePipeline {
+        self.store_pipelines[format.index()].get_or_init(|| {
+            let classes_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Classes Shader"),
+                source: wgpu::ShaderSource::Wgsl(format.classes_shader().into()),
+            });
+
+            let pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("classes pipeline layout"),
+                    bind_group_layouts: &[&init.bind_group_layout, &self.bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Store Classes Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &classes_shader,
+                entry_point: "store_classes".into(),
+                compilation_options: Default::default(),
+                cache: Default::default(),
+            })
+        })
+    }
+}
+
+/// The value of every component in multi-value mode, one entry per possible component.
+pub(crate) struct Classes {
+    classes_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Number of components the buffer can hold.
+    capacity: u64,
+}
+
+impl Classes {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &ClassesPipelines, capacity: u64) -> Classes {
+        let classes_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Classes Buffer"),
+            size: capacity * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("classes_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: classes_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            classes_buffer,
+            bind_group,
+            capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub(crate) fn buffer(&self) -> &wgpu::Buffer {
+        &self.classes_buffer
+    }
+
+    /// Records the pass, which has to run after the relabel stage. Every entry of a
+    /// component is overwritten, so the buffer does not need to be cleared.
+    /// `store_pipeline` has to match the format of the input bound with `init_bind_group`,
+    /// see [`ClassesPipelines::store_pipeline`].
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, store_pipeline: &wgpu::ComputePipeline, init_bind_group: &wgpu::BindGroup, workgroups: (u32, u32)) {
+        compute_pass.set_pipeline(store_pipeline);
+        compute_pass.set_bind_group(0, init_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+}
@@ -0,0 +1,212 @@
+use wesl::include_wesl;
+
+/// How labels are mapped to colors, see [`ColorOptions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Palette {
+    /// Steps around the hue circle by the golden ratio, which spreads consecutive labels
+    /// evenly. Best with [`crate::CCLOptions::relabel`].
+    #[default]
+    GoldenRatio,
+    /// Hashes every label to a random color.
+    Hash,
+}
+
+/// Options of the colorized visualization, see [`crate::CCLOptions::colorize`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColorOptions {
+    pub palette: Palette,
+    /// Changes the colors, the same seed always gives the same colors.
+    pub seed: u32,
+    /// Makes the background transparent instead of black.
+    pub transparent_background: bool,
+}
+
+impl ColorOptions {
+    fn to_params(self) -> ColorParams {
+        ColorParams {
+            seed: self.seed,
+            palette: match self.palette {
+                Palette::GoldenRatio => 0,
+                Palette::Hash => 1,
+            },
+            transparent: self.transparent_background as u32,
+            _pad0: 0,
+        }
+    }
+}
+
+/// The `ColorParams` uniform of `colorize.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorParams {
+    seed: u32,
+    palette: u32,
+    transparent: u32,
+    _pad0: u32,
+}
+
+pub(crate) struct ColorizePipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    colorize_pipeline: wgpu::ComputePipeline,
+}
+
+impl ColorizePipelines {
+    pub(crate) fn new(device: &wgpu::Device) -> ColorizePipelines {
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("colorize_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba8Unorm,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    // dimensions and color params
+                    uniform_entry(2),
+                    uniform_entry(3),
+                ],
+            });
+
+        let shader_string = include_wesl!("colorize");
+        let colorize_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Colorize Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("colorize pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let colorize_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Colorize Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &colorize_shader,
+            entry_point: "colorize".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            colorize_pipeline,
+        }
+    }
+}
+
+/// The Rgba8Unorm texture the colorized labels are written to.
+pub(crate) struct Colorized {
+    texture: wgpu::Texture,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Colorized {
+    /// Creates the texture for a `width` x `height` image, or reuses the one of `previous`
+    /// if it has that size. The bind group is always recreated, the labels buffer might be
+    /// new.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &ColorizePipelines,
+        previous: Option<Colorized>,
+        (width, height): (u32, u32),
+        labels_buffer: &wgpu::Buffer,
+        dims_buffer: &wgpu::Buffer,
+        options: ColorOptions,
+    ) -> Colorized {
+        let (texture, params_buffer) = match previous {
+            Some(previous) if previous.texture.width() == width && previous.texture.height() == height => (previous.texture, previous.params_buffer),
+            _ => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Colorized Labels Texture"),
+                    size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Color Params Uniform"),
+                    size: std::mem::size_of::<ColorParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                (texture, params_buffer)
+            }
+        };
+        queue.write_buffer(&params_buffer, 0, bytemuck::cast_slice(&[options.to_params()]));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: labels_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: dims_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("colorize_bind_group"),
+        });
+
+        Self {
+            texture,
+            params_buffer,
+            bind_group,
+        }
+    }
+
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Records the pass, which has to run after the relabel stage to color the
+    /// consecutive labels.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &ColorizePipelines) {
+        compute_pass.set_pipeline(&pipelines.colorize_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.texture.width().div_ceil(8), self.texture.height().div_ceil(8), 1);
+    }
+}
@@ -0,0 +1,192 @@
+//! The outer contours of the components, see [`crate::CCLOptions::contours`].
+//!
+//! The boundary pixels are marked on the GPU, the contours are traced along them on the
+//! CPU with Moore neighbour tracing.
+
+use std::collections::BTreeMap;
+
+use wesl::include_wesl;
+
+use crate::{LabelMap, bit_mask::BitMask};
+
+/// The outer boundary of one component.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contour {
+    /// The boundary pixels in clockwise order, starting at the first pixel of the component
+    /// in raster order. The contour is closed, the last point is followed by the first.
+    pub points: Vec<(u32, u32)>,
+    /// The length of the closed contour, with diagonal steps counting sqrt(2). 0 for a
+    /// single pixel.
+    pub perimeter: f64,
+}
+
+/// The 8 neighbours in clockwise order, starting with the one to the right. y points down.
+const NEIGHBOURS: [(i64, i64); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const WEST: usize = 4;
+
+/// Marks the pixels with a 4-neighbour of another component, of the background or
+/// outside of the image, like the GPU does.
+pub fn boundary_mask(label_map: &LabelMap) -> Vec<bool> {
+    let (width, height) = (label_map.width() as i64, label_map.height() as i64);
+    let label_at = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            0
+        } else {
+            label_map[(x as u32, y as u32)]
+        }
+    };
+    label_map
+        .iter()
+        .map(|(x, y, label)| {
+            let (x, y) = (x as i64, y as i64);
+            label != 0 && [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|&(dx, dy)| label_at(x + dx, y + dy) != label)
+        })
+        .collect()
+}
+
+/// Traces the outer contour of every component, keyed by label. `boundary` marks the
+/// boundary pixels, see [`boundary_mask`] and [`crate::CCLState::read_boundary`].
+pub fn trace_contours(label_map: &LabelMap, boundary: &[bool]) -> BTreeMap<u32, Contour> {
+    let mut contours = BTreeMap::new();
+    let width = label_map.width();
+    for (idx, _) in boundary.iter().enumerate().filter(|&(_, &set)| set) {
+        let label = label_map.as_slice()[idx];
+        // the first pixel of a component in raster order lies on its outer contour
+        if label != 0 && !contours.contains_key(&label) {
+            let start = (idx as u32 % width, idx as u32 / width);
+            contours.insert(label, trace(label_map, label, start));
+        }
+    }
+    contours
+}
+
+/// Moore neighbour tracing from `start`, which has no pixel of the component to its left
+/// or above it. Stops when the first step is taken a second time.
+fn trace(label_map: &LabelMap, label: u32, start: (u32, u32)) -> Contour {
+    let (width, height) = (label_map.width() as i64, label_map.height() as i64);
+    let inside = |(x, y): (i64, i64)| x >= 0 && y >= 0 && x < width && y < height && label_map[(x as u32, y as u32)] == label;
+
+    let start = (start.0 as i64, start.1 as i64);
+    let mut points = vec![start];
+    let mut perimeter = 0.0;
+    let mut current = start;
+    // the direction from the current pixel to the last pixel outside the component
+    let mut backtrack = WEST;
+    let mut first_step = None;
+    // a single pixel has no neighbour to step to
+    while let Some(direction) = (1..=8).map(|k| (backtrack + k) % 8).find(|&d| inside(step(current, d))) {
+        if first_step == Some((current, direction)) {
+            break;
+        }
+        if first_step.is_none() {
+            first_step = Some((current, direction));
+        }
+
+        let next = step(current, direction);
+        perimeter += if direction % 2 == 0 { 1.0 } else { std::f64::consts::SQRT_2 };
+        // the neighbour checked before `next` was outside, seen from `next`
+        let outside = step(current, (direction + 7) % 8);
+        backtrack = NEIGHBOURS
+            .iter()
+            .position(|&(dx, dy)| (next.0 + dx, next.1 + dy) == outside)
+            .expect("consecutive neighbours are adjacent");
+        current = next;
+        points.push(current);
+    }
+    // the walk ends on the start pixel, which is already the first point
+    if points.len() > 1 {
+        points.pop();
+    }
+
+    Contour {
+        points: points.into_iter().map(|(x, y)| (x as u32, y as u32)).collect(),
+        perimeter,
+    }
+}
+
+fn step((x, y): (i64, i64), direction: usize) -> (i64, i64) {
+    let (dx, dy) = NEIGHBOURS[direction];
+    (x + dx, y + dy)
+}
+
+pub(crate) struct BoundaryPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    mark_boundary_pipeline: wgpu::ComputePipeline,
+}
+
+impl BoundaryPipelines {
+    /// `merge_bind_group_layout` is bound to group 0, only the labels and the dimensions
+    /// are read.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> BoundaryPipelines {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("boundary_bind_group_layout"),
+                entries: &[BitMask::layout_entry(0)],
+            });
+
+        let shader_string = include_wesl!("boundary");
+        let boundary_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Boundary Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("boundary pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let mark_boundary_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Mark Boundary Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &boundary_shader,
+            entry_point: "mark_boundary".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            mark_boundary_pipeline,
+        }
+    }
+}
+
+/// The bit-packed boundary pixels of the bound image.
+pub(crate) struct Boundary {
+    mask: BitMask,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Boundary {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &BoundaryPipelines, capacity: u64) -> Boundary {
+        let mask = BitMask::new(device, "Boundary Buffer", capacity);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("boundary_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[mask.entry(0)],
+        });
+
+        Self {
+            mask,
+            bind_group,
+        }
+    }
+
+    pub(crate) fn mask(&self) -> &BitMask {
+        &self.mask
+    }
+
+    pub(crate) fn mask_mut(&mut self) -> &mut BitMask {
+        &mut self.mask
+    }
+
+    /// Records the pass, which has to run after every stage that changes the labels.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &BoundaryPipelines, merge_bind_group: &wgpu::BindGroup, (width, height): (u32, u32)) {
+        compute_pass.set_pipeline(&pipelines.mark_boundary_pipeline);
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+    }
+}
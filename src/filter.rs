@@ -0,0 +1,366 @@
+use wesl::include_wesl;
+
+use crate::{Input, bit_mask::BitMask};
+
+/// The `FilterParams` uniform of `filter.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterParams {
+    min_area: u32,
+    select_largest: u32,
+    largest_count: u32,
+    clear_border: u32,
+}
+
+/// Size of the `SelectState` of `filter.wesl`.
+const SELECT_STATE_SIZE: u64 = 6 * 4;
+
+/// The filters of [`crate::CCLOptions`], a component has to pass all of them to be kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Filters {
+    pub(crate) min_area: Option<u32>,
+    pub(crate) keep_largest: Option<u32>,
+    pub(crate) clear_border: bool,
+    pub(crate) clear_input: bool,
+}
+
+pub(crate) struct FilterPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    clear_input_bind_group_layout: wgpu::BindGroupLayout,
+    apply_pipeline: wgpu::ComputePipeline,
+    apply_mark_removed_pipeline: wgpu::ComputePipeline,
+    clear_input_pipeline: wgpu::ComputePipeline,
+    start_select_area_pipeline: wgpu::ComputePipeline,
+    count_select_area_pipeline: wgpu::ComputePipeline,
+    update_select_area_pipeline: wgpu::ComputePipeline,
+    count_select_larger_pipeline: wgpu::ComputePipeline,
+    start_select_label_pipeline: wgpu::ComputePipeline,
+    count_select_label_pipeline: wgpu::ComputePipeline,
+    update_select_label_pipeline: wgpu::ComputePipeline,
+}
+
+impl FilterPipelines {
+    /// `merge_bind_group_layout` is bound to group 0, the filter sets the labels of the
+    /// removed components to 0 in place.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> FilterPipelines {
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("filter_bind_group_layout"),
+                entries: &[
+                    // the stats and the mask
+                    storage_entry(0, false),
+                    BitMask::layout_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+                        },
+                        count: None,
+                    },
+                    // the selection of the largest components and the component count
+                    storage_entry(3, false),
+                    storage_entry(4, true),
+                ],
+            });
+
+        let clear_input_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("filter_clear_input_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba8Uint,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    // the removed pixels
+                    BitMask::layout_entry(1),
+                ],
+            });
+
+        let shader_string = include_wesl!("filter");
+        let filter_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Filter Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("filter pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let clear_input_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("filter clear input pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout, &clear_input_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let apply_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Apply Filter Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &filter_shader,
+            entry_point: "apply_filter".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let apply_mark_removed_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Apply Filter Mark Removed Pipeline"),
+            layout: Some(&clear_input_pipeline_layout),
+            module: &filter_shader,
+            entry_point: "apply_filter_mark_removed".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let clear_input_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Clear Input Pipeline"),
+            layout: Some(&clear_input_pipeline_layout),
+            module: &filter_shader,
+            entry_point: "clear_input".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let pipeline = |label, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &filter_shader,
+                entry_point: entry_point.into(),
+                compilation_options: Default::default(),
+                cache: Default::default(),
+            })
+        };
+
+        Self {
+            bind_group_layout,
+            clear_input_bind_group_layout,
+            apply_pipeline,
+            apply_mark_removed_pipeline,
+            clear_input_pipeline,
+            start_select_area_pipeline: pipeline("Start Select Area Pipeline", "start_select_area"),
+            count_select_area_pipeline: pipeline("Count Select Area Pipeline", "count_select_area"),
+            update_select_area_pipeline: pipeline("Update Select Area Pipeline", "update_select_area"),
+            count_select_larger_pipeline: pipeline("Count Select Larger Pipeline", "count_select_larger"),
+            start_select_label_pipeline: pipeline("Start Select Label Pipeline", "start_select_label"),
+            count_select_label_pipeline: pipeline("Count Select Label Pipeline", "count_select_label"),
+            update_select_label_pipeline: pipeline("Update Select Label Pipeline", "update_select_label"),
+        }
+    }
+}
+
+/// The bit-packed mask of the components that pass the filters of
+/// [`crate::CCLOptions::min_area`], [`crate::CCLOptions::keep_largest`] and
+/// [`crate::CCLOptions::clear_border`].
+pub(crate) struct Filter {
+    mask: BitMask,
+    params_buffer: wgpu::Buffer,
+    select_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Set if the removed pixels are cleared in the input as well.
+    clear_input: Option<ClearInput>,
+    select_largest: bool,
+}
+
+/// The pixels removed by the filter and the bind group that clears them in the input.
+struct ClearInput {
+    removed: BitMask,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Filter {
+    /// Creates the mask for `capacity` pixels, or reuses the one of `previous` if it is
+    /// large enough. The bind groups are always recreated, the stats buffer might be new.
+    /// The input is only cleared if `clear_input` is set and it is an
+    /// [`crate::InputFormat::Rgba8`] texture.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &FilterPipelines,
+        previous: Option<Filter>,
+        (capacity, len): (u64, u64),
+        stats_buffer: &wgpu::Buffer,
+        count_buffer: &wgpu::Buffer,
+        input: &Input,
+        filters: Filters,
+    ) -> Filter {
+        // the removed mask is as large as the mask
+        let (mut mask, params_buffer, select_buffer, previous_removed) = match previous {
+            Some(previous) if previous.mask.capacity() >= capacity => {
+                let removed = previous.clear_input.map(|clear_input| clear_input.removed);
+                (previous.mask, previous.params_buffer, previous.select_buffer, removed)
+            }
+            _ => {
+                let mask = BitMask::new(device, "Filtered Mask Buffer", capacity);
+                let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Filter Params Uniform"),
+                    size: std::mem::size_of::<FilterParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let select_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Select State Buffer"),
+                    size: SELECT_STATE_SIZE,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                (mask, params_buffer, select_buffer, None)
+            }
+        };
+        mask.set_len(len);
+        let params = FilterParams {
+            min_area: filters.min_area.unwrap_or(0),
+            select_largest: filters.keep_largest.is_some() as u32,
+            largest_count: filters.keep_largest.unwrap_or(0),
+            clear_border: filters.clear_border as u32,
+        };
+        queue.write_buffer(&params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("filter_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: stats_buffer.as_entire_binding(),
+                },
+                mask.entry(1),
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: select_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: count_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let input_view = input.rgba8_view().filter(|_| filters.clear_input);
+        if filters.clear_input && input_view.is_none() {
+            log::warn!("only Rgba8 inputs can be cleared, the {:?} input is left as it is", input.format());
+        }
+        let clear_input = input_view.map(|view| {
+            let mut removed = previous_removed.unwrap_or_else(|| BitMask::new(device, "Removed Mask Buffer", capacity));
+            removed.set_len(len);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("filter_clear_input_bind_group"),
+                layout: &pipelines.clear_input_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    removed.entry(1),
+                ],
+            });
+            ClearInput { removed, bind_group }
+        });
+
+        Self {
+            mask,
+            params_buffer,
+            select_buffer,
+            bind_group,
+            clear_input,
+            select_largest: filters.keep_largest.is_some(),
+        }
+    }
+
+    pub(crate) fn mask(&self) -> &BitMask {
+        &self.mask
+    }
+
+    /// Clears the mask and the removed pixels of the bound image. Has to be recorded
+    /// outside of a compute pass.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.mask.clear(encoder);
+        if let Some(clear_input) = &self.clear_input {
+            clear_input.removed.clear(encoder);
+        }
+    }
+
+    /// Records the filter, which has to run after the stats stage. `pixel_workgroups`
+    /// covers every pixel with one thread, the selection of the largest components needs
+    /// one thread per component.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &FilterPipelines, merge_bind_group: &wgpu::BindGroup, workgroups: (u32, u32), pixel_workgroups: (u32, u32)) {
+        if self.select_largest {
+            compute_pass.set_bind_group(0, merge_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.bind_group, &[]);
+            // both searches halve a range of at most len + 1 values
+            let steps = u64::BITS - self.mask.len().leading_zeros() + 1;
+            let single = |compute_pass: &mut wgpu::ComputePass, pipeline| {
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+            };
+            let all = |compute_pass: &mut wgpu::ComputePass, pipeline| {
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+            };
+
+            single(compute_pass, &pipelines.start_select_area_pipeline);
+            for _ in 0..steps {
+                all(compute_pass, &pipelines.count_select_area_pipeline);
+                single(compute_pass, &pipelines.update_select_area_pipeline);
+            }
+            all(compute_pass, &pipelines.count_select_larger_pipeline);
+            single(compute_pass, &pipelines.start_select_label_pipeline);
+            for _ in 0..steps {
+                all(compute_pass, &pipelines.count_select_label_pipeline);
+                single(compute_pass, &pipelines.update_select_label_pipeline);
+            }
+        }
+
+        match &self.clear_input {
+            Some(clear_input) => {
+                compute_pass.set_pipeline(&pipelines.apply_mark_removed_pipeline);
+                compute_pass.set_bind_group(2, &clear_input.bind_group, &[]);
+            }
+            None => compute_pass.set_pipeline(&pipelines.apply_pipeline),
+        }
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+
+    /// Records the clearing of the removed pixels in the input with
+    /// [`crate::CCLOptions::clear_input`]. Has to run after every pass that reads the input,
+    /// so the background phase still sees the removed components. `pixel_workgroups` covers
+    /// every pixel with one thread.
+    pub(crate) fn dispatch_clear_input(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &FilterPipelines, merge_bind_group: &wgpu::BindGroup, pixel_workgroups: (u32, u32)) {
+        let Some(clear_input) = &self.clear_input else {
+            return;
+        };
+        compute_pass.set_pipeline(&pipelines.clear_input_pipeline);
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.set_bind_group(2, &clear_input.bind_group, &[]);
+        compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+    }
+}
@@ -0,0 +1,132 @@
+/// One channel of an RGBA texel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    #[default]
+    R,
+    G,
+    B,
+    A,
+}
+
+/// How a texel is tested for being foreground.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForegroundTest {
+    /// The channel is non-zero.
+    Channel(Channel),
+    /// Any of the red, green and blue channels is non-zero. Alpha is ignored, it is
+    /// non-zero for every pixel of an opaque image.
+    AnyChannel,
+    /// The Rec. 601 luminance of the red, green and blue channels is above the threshold.
+    Luminance { threshold: u8 },
+    /// The alpha channel is above the threshold.
+    Alpha { threshold: u8 },
+}
+
+impl Default for ForegroundTest {
+    fn default() -> Self {
+        ForegroundTest::Channel(Channel::R)
+    }
+}
+
+/// The value of a pixel in multi-value mode, see [`crate::CCLOptions::multi_value`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelValue {
+    /// The value of one channel, e.g. for class ids in the red channel.
+    Channel(Channel),
+    /// The whole texel as `r | g << 8 | b << 16 | a << 24`, e.g. for color coded maps.
+    Rgba,
+}
+
+impl PixelValue {
+    /// The same value `foreground.wesl` computes on the GPU.
+    pub fn of(&self, [r, g, b, a]: [u8; 4]) -> u32 {
+        match self {
+            PixelValue::Channel(channel) => [r, g, b, a][*channel as usize] as u32,
+            PixelValue::Rgba => u32::from_le_bytes([r, g, b, a]),
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            PixelValue::Channel(channel) => channel as u32 + 1,
+            PixelValue::Rgba => 5,
+        }
+    }
+}
+
+/// Which pixels of the input are labeled. By default every pixel with a non-zero red
+/// channel is foreground.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Foreground {
+    pub test: ForegroundTest,
+    /// Labels the pixels that fail the test instead.
+    pub inverted: bool,
+}
+
+impl Foreground {
+    pub fn new(test: ForegroundTest) -> Foreground {
+        Self { test, inverted: false }
+    }
+
+    pub fn inverted(self) -> Foreground {
+        Self { inverted: !self.inverted, ..self }
+    }
+
+    /// The same test `foreground.wesl` does on the GPU.
+    pub fn is_foreground(&self, [r, g, b, a]: [u8; 4]) -> bool {
+        let result = match self.test {
+            ForegroundTest::Channel(channel) => [r, g, b, a][channel as usize] != 0,
+            ForegroundTest::AnyChannel => r != 0 || g != 0 || b != 0,
+            ForegroundTest::Luminance { threshold } => luminance(r, g, b) > threshold as u32,
+            ForegroundTest::Alpha { threshold } => a > threshold,
+        };
+        result != self.inverted
+    }
+
+    /// `value` is only set in multi-value mode.
+    pub(crate) fn to_params(self, value: Option<PixelValue>) -> ForegroundParams {
+        let (mode, channel, threshold) = match self.test {
+            ForegroundTest::Channel(channel) => (0, channel as u32, 0),
+            ForegroundTest::AnyChannel => (1, 0, 0),
+            ForegroundTest::Luminance { threshold } => (2, 0, threshold as u32),
+            ForegroundTest::Alpha { threshold } => (3, 0, threshold as u32),
+        };
+        ForegroundParams {
+            mode,
+            channel,
+            threshold,
+            inverted: self.inverted as u32,
+            value: value.map_or(0, PixelValue::to_u32),
+            channels: 4,
+            _pad0: 0,
+            _pad1: 0,
+        }
+    }
+}
+
+/// Rounded integer luminance, so the CPU and the GPU agree on every pixel.
+fn luminance(r: u8, g: u8, b: u8) -> u32 {
+    (299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000
+}
+
+/// The `ForegroundParams` uniform of `foreground.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ForegroundParams {
+    mode: u32,
+    channel: u32,
+    threshold: u32,
+    inverted: u32,
+    value: u32,
+    channels: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+impl ForegroundParams {
+    /// The number of channels of an [`crate::InputFormat::Uint`] or
+    /// [`crate::InputFormat::Float`] input, which `input.wesl` expands to RGBA.
+    pub(crate) fn with_channels(self, channels: u32) -> ForegroundParams {
+        Self { channels, ..self }
+    }
+}
@@ -0,0 +1,137 @@
+use wesl::include_wesl;
+
+use crate::bit_mask::BitMask;
+
+pub(crate) struct HolesPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    mark_border_pipeline: wgpu::ComputePipeline,
+    fill_holes_pipeline: wgpu::ComputePipeline,
+}
+
+impl HolesPipelines {
+    /// `merge_bind_group_layout` is bound to group 0 with the buffers of the background.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> HolesPipelines {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("holes_bind_group_layout"),
+                entries: &[
+                    // one flag per background label
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BitMask::layout_entry(1),
+                ],
+            });
+
+        let shader_string = include_wesl!("holes");
+        let holes_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Holes Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("holes pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let mark_border_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Mark Border Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &holes_shader,
+            entry_point: "mark_border".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let fill_holes_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Fill Holes Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &holes_shader,
+            entry_point: "fill_holes".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            mark_border_pipeline,
+            fill_holes_pipeline,
+        }
+    }
+}
+
+/// The border flags of the background components and the filled mask of
+/// [`crate::CCLOptions::fill_holes`].
+pub(crate) struct Holes {
+    touches_border_buffer: wgpu::Buffer,
+    filled: BitMask,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Holes {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &HolesPipelines, capacity: u64) -> Holes {
+        // the background labels go up to the number of pixels without relabel
+        let touches_border_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Touches Border Buffer"),
+            size: capacity.max(1) * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let filled = BitMask::new(device, "Filled Mask Buffer", capacity);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("holes_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: touches_border_buffer.as_entire_binding(),
+                },
+                filled.entry(1),
+            ],
+        });
+
+        Self {
+            touches_border_buffer,
+            filled,
+            bind_group,
+        }
+    }
+
+    pub(crate) fn mask(&self) -> &BitMask {
+        &self.filled
+    }
+
+    pub(crate) fn mask_mut(&mut self) -> &mut BitMask {
+        &mut self.filled
+    }
+
+    /// Clears the flags and the mask of the bound image. Has to be recorded outside of a
+    /// compute pass.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        // one flag for every possible background label
+        encoder.clear_buffer(&self.touches_border_buffer, 0, Some(self.filled.len().max(1) * 4));
+        self.filled.clear(encoder);
+    }
+
+    /// Records the passes, which have to run after the background is labeled.
+    /// `background_merge_bind_group` is the merge bind group of the background.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &HolesPipelines, background_merge_bind_group: &wgpu::BindGroup, (width, height): (u32, u32)) {
+        compute_pass.set_bind_group(0, background_merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        compute_pass.set_pipeline(&pipelines.mark_border_pipeline);
+        compute_pass.dispatch_workgroups(width.max(height).div_ceil(64), 1, 1);
+
+        compute_pass.set_pipeline(&pipelines.fill_holes_pipeline);
+        compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+    }
+}
@@ -0,0 +1,385 @@
+use wesl::include_wesl;
+
+use crate::texture::TextureUInt;
+
+/// The formats an [`Input`] can have. Every format has its own variant of the init pass,
+/// see `input.wesl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputFormat {
+    /// An Rgba8Uint texture with storage binding usage, e.g. a [`TextureUInt`].
+    Rgba8,
+    /// An R8Uint texture with texture binding usage.
+    R8,
+    /// Any sampled texture with unsigned integer texels, read with `textureLoad` on a
+    /// `texture_2d<u32>`. Channels the format lacks read as 0 and alpha as 255.
+    Uint,
+    /// Any sampled unorm, snorm or float texture, e.g. a render target, read with
+    /// `textureLoad` on a `texture_2d<f32>`. The texels are clamped to [0, 1] and scaled to
+    /// 0..=255 like Rgba8 texels.
+    Float,
+    /// A storage buffer with one byte per pixel, row by row without padding.
+    Bytes,
+    /// A storage buffer with one bit per pixel, row by row without padding. Pixel `i` is
+    /// bit `i % 32` of the u32 at `i / 32`, see [`pack_bits`].
+    Bits,
+}
+
+impl InputFormat {
+    pub(crate) const ALL: [InputFormat; 6] = [
+        InputFormat::Rgba8,
+        InputFormat::R8,
+        InputFormat::Uint,
+        InputFormat::Float,
+        InputFormat::Bytes,
+        InputFormat::Bits,
+    ];
+
+    /// Index into [`InputFormat::ALL`].
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Number of bytes a buffer of this format needs for `num_pixels`, rounded up to whole
+    /// u32s. `None` for textures.
+    pub fn buffer_size(self, num_pixels: u64) -> Option<u64> {
+        match self {
+            InputFormat::Bytes => Some(num_pixels.div_ceil(4) * 4),
+            InputFormat::Bits => Some(num_pixels.div_ceil(32) * 4),
+            InputFormat::Rgba8 | InputFormat::R8 | InputFormat::Uint | InputFormat::Float => None,
+        }
+    }
+
+    fn binding_type(self) -> wgpu::BindingType {
+        match self {
+            InputFormat::Rgba8 => wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::ReadOnly,
+                format: wgpu::TextureFormat::Rgba8Uint,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            InputFormat::R8 | InputFormat::Uint => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Uint,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            // textureLoad does not filter, so this accepts filterable formats as well
+            InputFormat::Float => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            InputFormat::Bytes | InputFormat::Bits => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        }
+    }
+
+    pub(crate) fn init_shader(self) -> &'static str {
+        match self {
+            InputFormat::Rgba8 => include_wesl!("init_labeling"),
+            InputFormat::R8 => include_wesl!("init_labeling_r8"),
+            InputFormat::Uint => include_wesl!("init_labeling_uint"),
+            InputFormat::Float => include_wesl!("init_labeling_float"),
+            InputFormat::Bytes => include_wesl!("init_labeling_bytes"),
+            InputFormat::Bits => include_wesl!("init_labeling_bits"),
+        }
+    }
+
+    pub(crate) fn classes_shader(self) -> &'static str {
+        match self {
+            InputFormat::Rgba8 => include_wesl!("classes"),
+            InputFormat::R8 => include_wesl!("classes_r8"),
+            InputFormat::Uint => include_wesl!("classes_uint"),
+            InputFormat::Float => include_wesl!("classes_float"),
+            InputFormat::Bytes => include_wesl!("classes_bytes"),
+            InputFormat::Bits => include_wesl!("classes_bits"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum InputResource {
+    Texture(wgpu::TextureView),
+    Buffer(wgpu::Buffer),
+}
+
+/// The image a [`crate::CCLState`] labels. Single-channel inputs read as grey,
+/// `(value, value, value, 255)`, for the [`crate::Foreground`] test and a bit as 0 or 1
+/// in every color channel. The luminance of a single channel is its value.
+#[derive(Clone, Debug)]
+pub struct Input {
+    format: InputFormat,
+    width: u32,
+    height: u32,
+    // the number of channels of the texels, 1 for the buffer formats
+    channels: u32,
+    resource: InputResource,
+}
+
+impl Input {
+    /// Wraps an R8Uint texture that has texture binding usage.
+    pub fn r8_texture(texture: &wgpu::Texture) -> anyhow::Result<Input> {
+        anyhow::ensure!(texture.format() == wgpu::TextureFormat::R8Uint, "expected an R8Uint texture, got {:?}", texture.format());
+        anyhow::ensure!(texture.usage().contains(wgpu::TextureUsages::TEXTURE_BINDING), "the texture needs TEXTURE_BINDING usage");
+        Ok(Self {
+            format: InputFormat::R8,
+            width: texture.width(),
+            height: texture.height(),
+            channels: 1,
+            resource: InputResource::Texture(texture.create_view(&wgpu::TextureViewDescriptor::default())),
+        })
+    }
+
+    /// Creates a view of a 2D texture with texture binding usage, e.g. a render target,
+    /// and wraps it. The view has to be a single 2D layer of a texture that is not
+    /// multisampled, its first mip level is labeled. Unorm, snorm and float formats are
+    /// read as [`InputFormat::Float`], unsigned integer formats as [`InputFormat::Uint`]
+    /// and R8Uint as [`InputFormat::R8`].
+    pub fn texture_view(texture: &wgpu::Texture, descriptor: &wgpu::TextureViewDescriptor) -> anyhow::Result<Input> {
+        anyhow::ensure!(texture.dimension() == wgpu::TextureDimension::D2, "expected a 2D texture, got {:?}", texture.dimension());
+        anyhow::ensure!(texture.sample_count() == 1, "multisampled textures can not be labeled, resolve them first");
+        anyhow::ensure!(texture.usage().contains(wgpu::TextureUsages::TEXTURE_BINDING), "the texture needs TEXTURE_BINDING usage");
+        // the dimension wgpu picks for a view without one
+        let dimension = descriptor.dimension.unwrap_or(match texture.depth_or_array_layers() {
+            1 => wgpu::TextureViewDimension::D2,
+            _ => wgpu::TextureViewDimension::D2Array,
+        });
+        anyhow::ensure!(dimension == wgpu::TextureViewDimension::D2, "expected a 2D view, got {dimension:?}");
+        anyhow::ensure!(descriptor.base_mip_level < texture.mip_level_count(), "the texture has no mip level {}", descriptor.base_mip_level);
+        let view_format = descriptor.format.unwrap_or(texture.format());
+        let format = match view_format.sample_type(None, None) {
+            _ if view_format == wgpu::TextureFormat::R8Uint => InputFormat::R8,
+            Some(wgpu::TextureSampleType::Uint) => InputFormat::Uint,
+            Some(wgpu::TextureSampleType::Float { .. }) => InputFormat::Float,
+            _ => anyhow::bail!("{view_format:?} textures can not be labeled"),
+        };
+        let size = texture.size().mip_level_size(descriptor.base_mip_level, texture.dimension());
+        Ok(Self {
+            format,
+            width: size.width,
+            height: size.height,
+            channels: view_format.components() as u32,
+            resource: InputResource::Texture(texture.create_view(descriptor)),
+        })
+    }
+
+    /// Wraps a storage buffer of the [`InputFormat::Bytes`] or [`InputFormat::Bits`] format.
+    pub fn buffer(buffer: &wgpu::Buffer, format: InputFormat, width: u32, height: u32) -> anyhow::Result<Input> {
+        let Some(size) = format.buffer_size(width as u64 * height as u64) else {
+            anyhow::bail!("{format:?} is not a buffer format");
+        };
+        anyhow::ensure!(buffer.size() >= size, "a {width}x{height} image needs {size} bytes, the buffer has {}", buffer.size());
+        anyhow::ensure!(buffer.usage().contains(wgpu::BufferUsages::STORAGE), "the buffer needs STORAGE usage");
+        Ok(Self {
+            format,
+            width,
+            height,
+            channels: 1,
+            resource: InputResource::Buffer(buffer.clone()),
+        })
+    }
+
+    /// Uploads one byte per pixel into an R8Uint texture.
+    pub fn upload_r8(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, pixels: &[u8]) -> anyhow::Result<Input> {
+        anyhow::ensure!(pixels.len() as u64 == width as u64 * height as u64, "the number of pixels does not match the dimensions");
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("R8 Input Texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width),
+                rows_per_image: Some(height),
+            },
+            texture.size(),
+        );
+        Self::r8_texture(&texture)
+    }
+
+    /// Uploads one byte per pixel into a storage buffer.
+    pub fn upload_bytes(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, pixels: &[u8]) -> anyhow::Result<Input> {
+        anyhow::ensure!(pixels.len() as u64 == width as u64 * height as u64, "the number of pixels does not match the dimensions");
+        let mut words = vec![0u32; pixels.len().div_ceil(4)];
+        bytemuck::cast_slice_mut::<u32, u8>(&mut words)[..pixels.len()].copy_from_slice(pixels);
+        Self::upload_words(device, queue, InputFormat::Bytes, width, height, &words)
+    }
+
+    /// Uploads a mask with one bit per pixel into a storage buffer, which takes 1/32 of
+    /// the memory of an Rgba8Uint texture.
+    pub fn upload_bits(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, mask: &[bool]) -> anyhow::Result<Input> {
+        anyhow::ensure!(mask.len() as u64 == width as u64 * height as u64, "the number of pixels does not match the dimensions");
+        Self::upload_words(device, queue, InputFormat::Bits, width, height, &pack_bits(mask))
+    }
+
+    fn upload_words(device: &wgpu::Device, queue: &wgpu::Queue, format: InputFormat, width: u32, height: u32, words: &[u32]) -> anyhow::Result<Input> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Input Buffer"),
+            // a binding can not be empty
+            size: (words.len() as u64 * 4).max(4),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(words));
+        Self::buffer(&buffer, format, width, height)
+    }
+
+    pub fn format(&self) -> InputFormat {
+        self.format
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub(crate) fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// The view of an [`InputFormat::Rgba8`] texture, which can be written as storage
+    /// texture as well.
+    pub(crate) fn rgba8_view(&self) -> Option<&wgpu::TextureView> {
+        match (&self.resource, self.format) {
+            (InputResource::Texture(view), InputFormat::Rgba8) => Some(view),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        match &self.resource {
+            InputResource::Texture(view) => wgpu::BindingResource::TextureView(view),
+            InputResource::Buffer(buffer) => buffer.as_entire_binding(),
+        }
+    }
+}
+
+impl From<&TextureUInt> for Input {
+    fn from(texture_bundle: &TextureUInt) -> Input {
+        let size = texture_bundle.texture.size();
+        Self {
+            format: InputFormat::Rgba8,
+            width: size.width,
+            height: size.height,
+            channels: 4,
+            resource: InputResource::Texture(texture_bundle.view.clone()),
+        }
+    }
+}
+
+/// Packs a mask into the layout of [`InputFormat::Bits`].
+pub fn pack_bits(mask: &[bool]) -> Vec<u32> {
+    mask.chunks(32)
+        .map(|chunk| chunk.iter().enumerate().fold(0, |word, (bit, &set)| word | (set as u32) << bit))
+        .collect()
+}
+
+/// Unpacks the first `len` pixels of a mask in the layout of [`InputFormat::Bits`].
+pub fn unpack_bits(words: &[u32], len: usize) -> Vec<bool> {
+    (0..len).map(|idx| words[idx / 32] >> (idx % 32) & 1 != 0).collect()
+}
+
+/// The init bind group layout and pipeline of one input format.
+pub(crate) struct InitPipelines {
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) pipeline: wgpu::ComputePipeline,
+}
+
+impl InitPipelines {
+    pub(crate) fn new(device: &wgpu::Device, format: InputFormat) -> InitPipelines {
+        Self::with_input(device, format.binding_type(), format.init_shader())
+    }
+
+    /// The init pass of the layers of a [`crate::BatchState`], a 2D array texture with
+    /// unsigned integer texels.
+    pub(crate) fn batch(device: &wgpu::Device) -> InitPipelines {
+        let binding_type = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Uint,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+            multisampled: false,
+        };
+        Self::with_input(device, binding_type, include_wesl!("init_labeling_uint_array"))
+    }
+
+    /// `shader` is the variant of `init_labeling.wesl` that reads an input bound as
+    /// `input_binding`.
+    fn with_input(device: &wgpu::Device, input_binding: wgpu::BindingType, shader: &str) -> InitPipelines {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: std::num::NonZeroU64::new(size),
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("init_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: input_binding,
+                        count: None,
+                    },
+                    // labels, dimensions, infos and foreground test, the dimensions at
+                    // binding 2 like in every other bind group, see dims.wesl
+                    buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+                    uniform_entry(2, 16),
+                    buffer_entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+                    uniform_entry(4, 32),
+                ],
+            });
+
+        let init_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Init Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Init pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("init Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &init_shader,
+            entry_point: "init_labeling".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::{collections::HashMap, ops::Index};
+
+/// The labels of an image read back to the CPU.
+///
+/// Background pixels are `0`, every foreground pixel carries the label of its connected
+/// component. Labels are stored row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelMap {
+    width: u32,
+    height: u32,
+    labels: Vec<u32>,
+}
+
+impl LabelMap {
+    pub fn new(width: u32, height: u32, labels: Vec<u32>) -> LabelMap {
+        assert_eq!(
+            labels.len() as u64,
+            width as u64 * height as u64,
+            "the number of labels does not match the dimensions"
+        );
+        Self { width, height, labels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The label at column `x` and row `y`, or `None` if the position is outside the image.
+    pub fn get(&self, x: u32, y: u32) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.labels[self.index_of(x, y)])
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over all pixels in row-major order as `(x, y, label)`.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, u32)> + '_ {
+        let width = self.width;
+        self.labels
+            .iter()
+            .enumerate()
+            .map(move |(i, &label)| (i as u32 % width, i as u32 / width, label))
+    }
+
+    pub fn as_slice(&self) -> &[u32] {
+        &self.labels
+    }
+
+    pub fn into_vec(self) -> Vec<u32> {
+        self.labels
+    }
+
+    /// Whether both maps have the same background and split the foreground into the same
+    /// components, no matter which label each component carries. Labels of different
+    /// runs only match up to such a renaming, e.g. with and without relabeling.
+    pub fn same_components(&self, other: &LabelMap) -> bool {
+        self.width == other.width && self.height == other.height && same_components(&self.labels, &other.labels)
+    }
+
+    fn index_of(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+/// Whether two label buffers of the same dimensions only differ by a renaming of the labels,
+/// see [`LabelMap::same_components`].
+pub(crate) fn same_components(a: &[u32], b: &[u32]) -> bool {
+    let mut forward = HashMap::new();
+    let mut backward = HashMap::new();
+    a.iter().zip(b).all(|(&a, &b)| {
+        if (a == 0) != (b == 0) {
+            return false;
+        }
+        *forward.entry(a).or_insert(b) == b && *backward.entry(b).or_insert(a) == a
+    })
+}
+
+impl Index<(u32, u32)> for LabelMap {
+    type Output = u32;
+
+    /// The label at `(x, y)`. Panics if the position is outside the image.
+    fn index(&self, (x, y): (u32, u32)) -> &u32 {
+        assert!(x < self.width && y < self.height, "({x}, {y}) is outside the label map");
+        &self.labels[self.index_of(x, y)]
+    }
+}
@@ -0,0 +1,87 @@
+/// The texture label_to_rgba writes the labels to, with the bytes of every label packed
+/// into the channels of one Rgba8Uint texel, least significant byte in red.
+pub(crate) struct LabelRgba {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    /// Whether the texture was created by the state rather than supplied by the caller.
+    owned: bool,
+}
+
+impl LabelRgba {
+    /// Creates a texture for a `width` x `height` image, or binds `target` if the caller
+    /// supplied one.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        target: Option<&wgpu::Texture>,
+        (width, height): (u32, u32),
+        labels_buffer: &wgpu::Buffer,
+        dims_buffer: &wgpu::Buffer,
+    ) -> LabelRgba {
+        let texture = match target {
+            Some(texture) => texture.clone(),
+            None => device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Label RGBA Texture"),
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Uint,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }),
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: labels_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: dims_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("label_to_rgba_bind_group"),
+        });
+
+        Self {
+            texture,
+            bind_group,
+            owned: target.is_none(),
+        }
+    }
+
+    /// Whether the owned texture can be reused for an image of `size`.
+    pub(crate) fn fits(&self, (width, height): (u32, u32)) -> bool {
+        self.owned && self.texture.width() == width && self.texture.height() == height
+    }
+
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// Checks that the caller supplied `target` can hold the labels of a `width` x `height`
+/// image.
+pub(crate) fn check_target(target: &wgpu::Texture, (width, height): (u32, u32)) -> anyhow::Result<()> {
+    anyhow::ensure!(target.format() == wgpu::TextureFormat::Rgba8Uint, "expected an Rgba8Uint texture, got {:?}", target.format());
+    anyhow::ensure!(target.usage().contains(wgpu::TextureUsages::STORAGE_BINDING), "the texture needs STORAGE_BINDING usage");
+    anyhow::ensure!(
+        target.width() >= width && target.height() >= height,
+        "a {}x{} texture can not hold the labels of a {width}x{height} image",
+        target.width(),
+        target.height()
+    );
+    Ok(())
+}
@@ -0,0 +1,396 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::contours::{self, Contour};
+use crate::overlay::{self, OverlayOptions, OverlayRenderer};
+use crate::tree::ComponentTree;
+use crate::volume::{self, VolumeLabels, VolumeOptions, VolumeState};
+use crate::batch::{self, BatchOptions, BatchState};
+use crate::{CCLOptions, CCLOutput, CCLPipelines, CCLState, Channel, ColorOptions, ComponentStats, Connectivity, Input, LabelMap, Phase, PixelValue, texture};
+
+/// Owns a device, its queue, the compiled pipelines and a [`CCLState`] that is reused
+/// between images, so labeling an image is a single call.
+pub struct Labeler {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipelines: Arc<CCLPipelines>,
+    options: CCLOptions,
+    state: Option<CCLState>,
+    /// Created on the first overlay.
+    overlay: Option<OverlayRenderer>,
+    /// Created on the first volume.
+    volume: Option<VolumeState>,
+    /// Created on the first batch.
+    batch: Option<BatchState>,
+}
+
+impl Labeler {
+    /// Requests the default adapter and creates a device for it. Blocks until done.
+    pub fn new() -> anyhow::Result<Labeler> {
+        pollster::block_on(Self::new_async())
+    }
+
+    pub async fn new_async() -> anyhow::Result<Labeler> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::PRIMARY,
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await?;
+        let (device, queue) = adapter.request_device(&Default::default()).await?;
+
+        Ok(Self::with_device(&device, &queue))
+    }
+
+    /// Labels on a device the caller already has.
+    pub fn with_device(device: &wgpu::Device, queue: &wgpu::Queue) -> Labeler {
+        let pipelines = Arc::new(CCLPipelines::new(device));
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            pipelines,
+            options: CCLOptions::default(),
+            state: None,
+            overlay: None,
+            volume: None,
+            batch: None,
+        }
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Sets the optional stages used for the following images.
+    pub fn set_options(&mut self, options: CCLOptions) {
+        self.options = options;
+    }
+
+    /// Uploads `img`, labels it and reads the labels back. Blocks until done.
+    pub fn label(&mut self, img: &image::DynamicImage) -> anyhow::Result<LabelMap> {
+        pollster::block_on(self.label_async(img))
+    }
+
+    pub async fn label_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<LabelMap> {
+        self.run(img, self.options)?;
+        let state = self.state.as_ref().expect("run creates the state");
+        state.read_labels(&self.device, &self.queue).await
+    }
+
+    /// Labels an input that is already on the GPU, e.g. a bit-packed mask uploaded with
+    /// [`Input::upload_bits`] on this labeler's device. Blocks until done.
+    pub fn label_input(&mut self, input: impl Into<Input>) -> anyhow::Result<LabelMap> {
+        pollster::block_on(self.label_input_async(input))
+    }
+
+    pub async fn label_input_async(&mut self, input: impl Into<Input>) -> anyhow::Result<LabelMap> {
+        self.run_input(input.into(), self.options)?;
+        let state = self.state.as_ref().expect("run creates the state");
+        state.read_labels(&self.device, &self.queue).await
+    }
+
+    /// Labels `img` and returns the statistics of every component next to the labels,
+    /// which are consecutive in this case. Blocks until done.
+    pub fn label_with_stats(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<ComponentStats>)> {
+        pollster::block_on(self.label_with_stats_async(img))
+    }
+
+    pub async fn label_with_stats_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<ComponentStats>)> {
+        self.run(img, CCLOptions { stats: true, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let label_map = state.read_labels(&self.device, &self.queue).await?;
+        let stats = state.read_stats(&self.device, &self.queue).await?;
+        Ok((label_map, stats))
+    }
+
+    /// Labels `img` in multi-value mode and returns the value of every component next to
+    /// the labels, the value at index `i` belongs to label `i + 1`. Uses
+    /// [`CCLOptions::multi_value`] if set and the red channel otherwise. Blocks until done.
+    pub fn label_with_classes(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<u32>)> {
+        pollster::block_on(self.label_with_classes_async(img))
+    }
+
+    pub async fn label_with_classes_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<u32>)> {
+        let multi_value = self.options.multi_value.or(Some(PixelValue::Channel(Channel::R)));
+        self.run(img, CCLOptions { multi_value, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let label_map = state.read_labels(&self.device, &self.queue).await?;
+        let classes = state.read_classes(&self.device, &self.queue).await?;
+        Ok((label_map, classes))
+    }
+
+    /// Labels the foreground and the background of `img` in one run and returns both label
+    /// maps, which number their components independently. Blocks until done.
+    pub fn label_both(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, LabelMap)> {
+        pollster::block_on(self.label_both_async(img))
+    }
+
+    pub async fn label_both_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, LabelMap)> {
+        self.run(img, CCLOptions { phase: Phase::Both, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let foreground = state.read_labels(&self.device, &self.queue).await?;
+        let background = state.read_background_labels(&self.device, &self.queue).await?;
+        Ok((foreground, background))
+    }
+
+    /// Labels `img` and returns the labels colorized with [`CCLOptions::colorize`], or the
+    /// default [`ColorOptions`] if it is not set. Blocks until done.
+    pub fn label_colorized(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, image::RgbaImage)> {
+        pollster::block_on(self.label_colorized_async(img))
+    }
+
+    pub async fn label_colorized_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, image::RgbaImage)> {
+        let colorize = self.options.colorize.or(Some(ColorOptions::default()));
+        self.run(img, CCLOptions { colorize, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let label_map = state.read_labels(&self.device, &self.queue).await?;
+        let colorized = state.read_colorized(&self.device, &self.queue).await?;
+        Ok((label_map, colorized))
+    }
+
+    /// Labels `img` and draws the colorized components over it, see [`OverlayRenderer`].
+    /// Blocks until done.
+    pub fn label_overlay(&mut self, img: &image::DynamicImage, overlay_options: OverlayOptions) -> anyhow::Result<(LabelMap, image::RgbaImage)> {
+        pollster::block_on(self.label_overlay_async(img, overlay_options))
+    }
+
+    pub async fn label_overlay_async(&mut self, img: &image::DynamicImage, overlay_options: OverlayOptions) -> anyhow::Result<(LabelMap, image::RgbaImage)> {
+        let colorize = self.options.colorize.or(Some(ColorOptions::default()));
+        let output = self.run(img, CCLOptions { colorize, ..self.options })?;
+        let source = overlay::upload_source(&self.device, &self.queue, img);
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let renderer = self.overlay.get_or_insert_with(|| OverlayRenderer::new(&self.device));
+        let overlay = renderer.render_image(&self.device, &self.queue, &source_view, &output, overlay_options).await?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let label_map = state.read_labels(&self.device, &self.queue).await?;
+        Ok((label_map, overlay))
+    }
+
+    /// Labels `img` and removes the components with fewer than `min_area` pixels. Returns
+    /// the labels of the remaining components and their mask. Blocks until done.
+    pub fn remove_small_components(&mut self, img: &image::DynamicImage, min_area: u32) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        pollster::block_on(self.remove_small_components_async(img, min_area))
+    }
+
+    pub async fn remove_small_components_async(&mut self, img: &image::DynamicImage, min_area: u32) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        self.run(img, CCLOptions { min_area: Some(min_area), ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let (mask, label_map) = state.read_filtered(&self.device, &self.queue).await?;
+        Ok((label_map, mask))
+    }
+
+    /// Labels `img` and keeps only the `count` largest components, ties go to the lower
+    /// label. Returns their labels and their mask. Blocks until done.
+    pub fn keep_largest(&mut self, img: &image::DynamicImage, count: u32) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        pollster::block_on(self.keep_largest_async(img, count))
+    }
+
+    pub async fn keep_largest_async(&mut self, img: &image::DynamicImage, count: u32) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        self.run(img, CCLOptions { keep_largest: Some(count), ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let (mask, label_map) = state.read_filtered(&self.device, &self.queue).await?;
+        Ok((label_map, mask))
+    }
+
+    /// Labels `img` and removes the components that touch the border of the image.
+    /// Returns the labels of the remaining components and their mask. Blocks until done.
+    pub fn clear_border(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        pollster::block_on(self.clear_border_async(img))
+    }
+
+    pub async fn clear_border_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, Vec<bool>)> {
+        self.run(img, CCLOptions { clear_border: true, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let (mask, label_map) = state.read_filtered(&self.device, &self.queue).await?;
+        Ok((label_map, mask))
+    }
+
+    /// Labels the background of `img` with `connectivity` and returns the foreground with
+    /// the background components that do not touch the border filled. Blocks until done.
+    pub fn fill_holes(&mut self, img: &image::DynamicImage, connectivity: Connectivity) -> anyhow::Result<Vec<bool>> {
+        pollster::block_on(self.fill_holes_async(img, connectivity))
+    }
+
+    pub async fn fill_holes_async(&mut self, img: &image::DynamicImage, connectivity: Connectivity) -> anyhow::Result<Vec<bool>> {
+        self.run(img, CCLOptions { fill_holes: Some(connectivity), ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        state.read_filled(&self.device, &self.queue).await
+    }
+
+    /// Labels `img` and returns the outer contour of every component next to the labels,
+    /// keyed by label. Blocks until done.
+    pub fn label_with_contours(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, BTreeMap<u32, Contour>)> {
+        pollster::block_on(self.label_with_contours_async(img))
+    }
+
+    pub async fn label_with_contours_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, BTreeMap<u32, Contour>)> {
+        self.run(img, CCLOptions { contours: true, ..self.options })?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let label_map = state.read_labels(&self.device, &self.queue).await?;
+        let boundary = state.read_boundary(&self.device, &self.queue).await?;
+        let contours = contours::trace_contours(&label_map, &boundary);
+        Ok((label_map, contours))
+    }
+
+    /// Labels the foreground and the background of `img` and returns both label maps with
+    /// the containment hierarchy of their components. The background is labeled with the
+    /// other connectivity than the foreground, so the regions nest. The filters of the
+    /// options are not applied, the removed components would be neither foreground nor
+    /// background and cut the regions they enclose off the tree. Blocks until done.
+    pub fn component_tree(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, LabelMap, ComponentTree)> {
+        pollster::block_on(self.component_tree_async(img))
+    }
+
+    pub async fn component_tree_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<(LabelMap, LabelMap, ComponentTree)> {
+        let options = CCLOptions {
+            relabel: true,
+            phase: Phase::Both,
+            background_connectivity: Some(self.options.connectivity.complement()),
+            fill_holes: None,
+            min_area: None,
+            keep_largest: None,
+            clear_border: false,
+            clear_input: false,
+            ..self.options
+        };
+        self.run(img, options)?;
+        let state = self.state.as_ref().expect("run creates the state");
+        let foreground = state.read_labels(&self.device, &self.queue).await?;
+        let background = state.read_background_labels(&self.device, &self.queue).await?;
+        let tree = ComponentTree::new(&foreground, &background);
+        Ok((foreground, background, tree))
+    }
+
+    /// Labels the volume stacked from `slices` and returns its labels with the number of
+    /// components. The frames of a video work the same way, with time as the third axis.
+    /// Blocks until done.
+    pub fn label_volume(&mut self, slices: &[image::DynamicImage], options: VolumeOptions) -> anyhow::Result<(VolumeLabels, u32)> {
+        pollster::block_on(self.label_volume_async(slices, options))
+    }
+
+    pub async fn label_volume_async(&mut self, slices: &[image::DynamicImage], options: VolumeOptions) -> anyhow::Result<(VolumeLabels, u32)> {
+        let texture = volume::upload_slices(&self.device, &self.queue, slices)?;
+        self.label_volume_texture_async(&texture, options).await
+    }
+
+    /// Like [`Labeler::label_volume`] for a 3D texture that is already on the GPU, see
+    /// [`VolumeState::with_pipelines`]. Blocks until done.
+    pub fn label_volume_texture(&mut self, texture: &wgpu::Texture, options: VolumeOptions) -> anyhow::Result<(VolumeLabels, u32)> {
+        pollster::block_on(self.label_volume_texture_async(texture, options))
+    }
+
+    pub async fn label_volume_texture_async(&mut self, texture: &wgpu::Texture, options: VolumeOptions) -> anyhow::Result<(VolumeLabels, u32)> {
+        let state = match self.volume.as_mut() {
+            Some(state) => {
+                state.set_input(&self.device, &self.queue, texture)?;
+                state
+            }
+            None => self.volume.insert(VolumeState::new(&self.device, texture)?),
+        };
+        state.set_options(&self.queue, options);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Volume Encoder"),
+        });
+        state.compute(&mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let labels = state.read_labels(&self.device, &self.queue).await?;
+        let count = state.read_component_count(&self.device, &self.queue).await?;
+        Ok((labels, count))
+    }
+
+    /// Labels equally sized images in a single submission, with the connectivity and the
+    /// foreground test of the options, and returns the labels and the number of components
+    /// of every image. Blocks until done.
+    pub fn label_batch(&mut self, images: &[image::DynamicImage]) -> anyhow::Result<(Vec<LabelMap>, Vec<u32>)> {
+        pollster::block_on(self.label_batch_async(images))
+    }
+
+    pub async fn label_batch_async(&mut self, images: &[image::DynamicImage]) -> anyhow::Result<(Vec<LabelMap>, Vec<u32>)> {
+        let texture = batch::upload_batch(&self.device, &self.queue, images)?;
+        self.label_batch_texture_async(&texture).await
+    }
+
+    /// Like [`Labeler::label_batch`] for an array texture that is already on the GPU, see
+    /// [`BatchState::with_pipelines`]. Blocks until done.
+    pub fn label_batch_texture(&mut self, texture: &wgpu::Texture) -> anyhow::Result<(Vec<LabelMap>, Vec<u32>)> {
+        pollster::block_on(self.label_batch_texture_async(texture))
+    }
+
+    pub async fn label_batch_texture_async(&mut self, texture: &wgpu::Texture) -> anyhow::Result<(Vec<LabelMap>, Vec<u32>)> {
+        let state = match self.batch.as_mut() {
+            Some(state) => {
+                state.set_input(&self.device, &self.queue, texture)?;
+                state
+            }
+            None => self.batch.insert(BatchState::with_pipelines(&self.device, self.pipelines.clone(), texture)?),
+        };
+        state.set_options(&self.queue, BatchOptions { connectivity: self.options.connectivity, foreground: self.options.foreground });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Batch Encoder"),
+        });
+        state.compute(&mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let labels = state.read_labels(&self.device, &self.queue).await?;
+        let counts = state.read_component_counts(&self.device, &self.queue).await?;
+        Ok((labels, counts))
+    }
+
+    /// Labels `img` but only reads back the number of components. Blocks until done.
+    pub fn count_components(&mut self, img: &image::DynamicImage) -> anyhow::Result<u32> {
+        pollster::block_on(self.count_components_async(img))
+    }
+
+    pub async fn count_components_async(&mut self, img: &image::DynamicImage) -> anyhow::Result<u32> {
+        self.run(img, self.options)?;
+        let state = self.state.as_ref().expect("run creates the state");
+        state.read_component_count(&self.device, &self.queue).await
+    }
+
+    /// Uploads `img` and submits the labeling, the results stay on the GPU.
+    fn run(&mut self, img: &image::DynamicImage, options: CCLOptions) -> anyhow::Result<CCLOutput> {
+        let texture_bundle = texture::TextureUInt::from_image(&self.device, &self.queue, img, Some("in_texture"))?;
+        self.run_input(Input::from(&texture_bundle), options)
+    }
+
+    /// Submits the labeling of an input that is already on the GPU.
+    fn run_input(&mut self, input: Input, options: CCLOptions) -> anyhow::Result<CCLOutput> {
+        let state = match self.state.as_mut() {
+            Some(state) => {
+                state.set_input(&self.device, &self.queue, input)?;
+                state
+            }
+            None => self.state.insert(CCLState::with_pipelines(&self.device, &self.queue, self.pipelines.clone(), input)?),
+        };
+        if state.options() != options {
+            state.set_options(&self.device, &self.queue, options);
+        }
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Label Encoder"),
+        });
+        let output = state.compute(&mut encoder)?;
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(output)
+    }
+}
+
+/// Labels a single image on the default adapter. Blocks until done.
+///
+/// This creates a device and compiles the pipelines on every call, use a [`Labeler`] to
+/// label more than one image.
+pub fn label(img: &image::DynamicImage) -> anyhow::Result<LabelMap> {
+    Labeler::new()?.label(img)
+}
+
+pub async fn label_async(img: &image::DynamicImage) -> anyhow::Result<LabelMap> {
+    Labeler::new_async().await?.label_async(img).await
+}
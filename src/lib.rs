@@ -1,215 +1,902 @@
 pub mod texture;
+pub mod pipelines;
+pub mod label_map;
+pub mod labeler;
+pub mod readback;
+mod relabel;
+mod scan;
+pub mod stats;
+pub mod moments;
+pub mod foreground;
+mod classes;
+mod label_rgba;
+mod filter;
+mod holes;
+mod bit_mask;
+pub mod background;
+pub mod input;
+pub mod colorize;
+pub mod overlay;
+pub mod contours;
+pub mod tree;
+pub mod volume;
+pub mod batch;
+
+use std::{collections::BTreeMap, sync::Arc};
 
 use wgpu::{BufferDescriptor, util::{BufferInitDescriptor, DeviceExt}};
-use wesl::include_wesl;
-
 
+use relabel::Relabel;
+use stats::{RawStats, Stats};
+use moments::{MomentSums, RawMoments};
+use classes::Classes;
+use background::Background;
+use label_rgba::LabelRgba;
+use colorize::Colorized;
+use filter::{Filter, Filters};
+use holes::Holes;
+use contours::Boundary;
+use input::InitPipelines;
+
+pub use pipelines::CCLPipelines;
+pub use label_map::LabelMap;
+pub use labeler::{Labeler, label, label_async};
+pub use stats::ComponentStats;
+pub use moments::Moments;
+pub use foreground::{Channel, Foreground, ForegroundTest, PixelValue};
+pub use background::Phase;
+pub use input::{Input, InputFormat, pack_bits, unpack_bits};
+pub use colorize::{ColorOptions, Palette};
+pub use overlay::{OverlayOptions, OverlayRenderer};
+pub use contours::Contour;
+pub use tree::{ComponentTree, Region};
+pub use volume::{VolumeConnectivity, VolumeLabels, VolumeOptions, VolumePipelines, VolumeState};
+pub use batch::{BatchOptions, BatchState};
+
+
+/// The `Dimensions` uniform of `dims.wesl`.
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct Dimensions {
     columns: u32,
     rows:    u32,
-    _pad0:   u32,
-    _pad1:   u32,
+    /// 4 or 8, see [`Connectivity`].
+    connectivity: u32,
+    /// 1 if every pixel is a node of its own instead of every 2x2 block.
+    pixel_nodes: u32,
+}
+
+/// Which neighbours of a pixel belong to the same component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the pixels above, below, to the left and to the right.
+    Four,
+    /// The diagonal pixels as well.
+    #[default]
+    Eight,
 }
 
+impl Connectivity {
+    fn as_u32(self) -> u32 {
+        match self {
+            Connectivity::Four => 4,
+            Connectivity::Eight => 8,
+        }
+    }
+
+    /// The other connectivity, the one the holes of a component are connected with.
+    pub fn complement(self) -> Connectivity {
+        match self {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        }
+    }
+}
+
+/// Optional stages that run after the labeling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CCLOptions {
+    /// Rewrite the labels to consecutive ids 1..=N, numbered in raster order of the first
+    /// pixel of every component. Without this a label is the index of its root + 1.
+    pub relabel: bool,
+    /// Accumulate the area, bounding box, centroid and hole count of every component, see
+    /// [`CCLState::read_stats`]. Implies `relabel`, the statistics are keyed by the
+    /// consecutive labels.
+    pub stats: bool,
+    /// Also accumulate the second order moments of every component, see
+    /// [`ComponentStats::moments`]. Implies `stats`.
+    pub moments: bool,
+    /// The connectivity of the foreground pixels, 8 by default.
+    pub connectivity: Connectivity,
+    /// Which pixels are foreground, every pixel with a non-zero red channel by default.
+    pub foreground: Foreground,
+    /// Connect neighbouring foreground pixels only if they have the same value, e.g. the
+    /// class id of a semantic segmentation. The value of every component is returned by
+    /// [`CCLState::read_classes`]. Implies `relabel`.
+    pub multi_value: Option<PixelValue>,
+    /// Label the foreground, the background or both.
+    pub phase: Phase,
+    /// Fill the holes of the foreground, the background components that do not touch the
+    /// border of the image, see [`CCLOutput::filled`]. The background is labeled with
+    /// this connectivity, for [`Phase::Both`] as well.
+    pub fill_holes: Option<Connectivity>,
+    /// The connectivity of the background with [`Phase::Both`], `connectivity` if not set.
+    /// With the other connectivity than the foreground the regions nest, see
+    /// [`ComponentTree`]. [`CCLOptions::fill_holes`] takes precedence.
+    pub background_connectivity: Option<Connectivity>,
+    /// Write the labels to an Rgba8Uint texture as well, the bytes of every label packed
+    /// into the channels of a texel. See [`CCLOutput::label_rgba`] and
+    /// [`CCLState::set_label_rgba_target`].
+    pub label_rgba: bool,
+    /// Write a distinct color for every component to an Rgba8Unorm texture, see
+    /// [`CCLOutput::colorized`] and [`CCLState::save_colorized`].
+    pub colorize: Option<ColorOptions>,
+    /// Remove the components with fewer pixels from the labels, see
+    /// [`CCLState::read_filtered`]. Implies `stats`, the removed components keep their
+    /// statistics and the remaining ones their labels.
+    pub min_area: Option<u32>,
+    /// Keep only this many of the largest components and remove the others from the
+    /// labels like [`CCLOptions::min_area`]. Ties go to the lower label. Only the
+    /// components that pass the other filters are candidates. Implies `stats`.
+    pub keep_largest: Option<u32>,
+    /// Remove the components that touch the border of the image like
+    /// [`CCLOptions::min_area`], see [`ComponentStats::touches_border`]. Implies `relabel`,
+    /// but only flags the components of the first and last rows and columns instead of
+    /// accumulating all the stats.
+    pub clear_border: bool,
+    /// Also clear the pixels of the removed components in the input, so it can be labeled
+    /// again without them. Only an [`InputFormat::Rgba8`] input can be written. The input
+    /// is cleared at the end of [`CCLState::compute`], after the background is labeled.
+    pub clear_input: bool,
+    /// Mark the boundary pixels of the components, to trace their contours with
+    /// [`CCLState::read_contours`].
+    pub contours: bool,
+}
+
+impl CCLOptions {
+    fn needs_relabel(&self) -> bool {
+        self.relabel || self.needs_stats() || self.multi_value.is_some()
+    }
+
+    /// Whether every pixel is a node of its own with `connectivity`. Otherwise all
+    /// foreground pixels of a 2x2 block are connected and the block is the node, which
+    /// needs far fewer unions.
+    fn pixel_nodes(&self, connectivity: Connectivity) -> bool {
+        connectivity == Connectivity::Four || self.multi_value.is_some()
+    }
+
+    /// Whether the background is labeled in a phase of its own.
+    fn labels_background(&self) -> bool {
+        self.phase == Phase::Both || self.fill_holes.is_some()
+    }
+
+    fn background_connectivity(&self) -> Connectivity {
+        self.fill_holes.or(self.background_connectivity).unwrap_or(self.connectivity)
+    }
+
+    /// Whether the stats buffer is needed, [`CCLOptions::clear_border`] alone only needs
+    /// its border flags.
+    fn needs_stats(&self) -> bool {
+        self.accumulates_stats() || self.needs_filter()
+    }
+
+    fn accumulates_stats(&self) -> bool {
+        self.stats || self.moments || self.min_area.is_some() || self.keep_largest.is_some()
+    }
+
+    fn needs_filter(&self) -> bool {
+        self.filters() != Filters::default()
+    }
+
+    fn filters(&self) -> Filters {
+        Filters {
+            min_area: self.min_area,
+            keep_largest: self.keep_largest,
+            clear_border: self.clear_border,
+            clear_input: false,
+        }
+    }
+
+    /// The test of the pixels the main labels are computed for.
+    fn labeled_foreground(&self) -> Foreground {
+        match self.phase {
+            Phase::Background => self.foreground.inverted(),
+            Phase::Foreground | Phase::Both => self.foreground,
+        }
+    }
+}
+
+/// The buffers written by [`CCLState::compute`].
+pub struct CCLOutput {
+    /// One u32 label per pixel, row by row. The buffer can be larger than the image, only
+    /// the first `width * height` labels belong to it.
+    pub labels: wgpu::Buffer,
+    /// A single u32, the number of connected components.
+    pub component_count: wgpu::Buffer,
+    /// The labels of the background with [`Phase::Both`] or [`CCLOptions::fill_holes`], a
+    /// range of their own that starts at 1 as well.
+    pub background_labels: Option<wgpu::Buffer>,
+    /// The number of background components, see [`CCLOutput::background_labels`].
+    pub background_count: Option<wgpu::Buffer>,
+    /// The texture the labels are packed into with [`CCLOptions::label_rgba`].
+    pub label_rgba: Option<wgpu::Texture>,
+    /// The colorized labels with [`CCLOptions::colorize`].
+    pub colorized: Option<wgpu::Texture>,
+    /// The pixels of the components that passed the filters, [`CCLOptions::min_area`],
+    /// [`CCLOptions::keep_largest`] and [`CCLOptions::clear_border`], one bit per pixel.
+    /// It can be labeled again with [`Input::buffer`] and [`InputFormat::Bits`].
+    pub mask: Option<wgpu::Buffer>,
+    /// The foreground with its holes filled with [`CCLOptions::fill_holes`], one bit per
+    /// pixel like [`CCLOutput::mask`].
+    pub filled: Option<wgpu::Buffer>,
+    /// The boundary pixels of the components with [`CCLOptions::contours`], one bit per
+    /// pixel like [`CCLOutput::mask`].
+    pub boundary: Option<wgpu::Buffer>,
+}
+
+/// The largest width and height of an image a [`CCLState`] labels. The moments of the
+/// pixels of a 2x2 block are summed in a u32, which can not overflow below this.
+pub const MAX_IMAGE_SIDE: u32 = 32768;
+
+fn check_size((width, height): (u32, u32)) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        width <= MAX_IMAGE_SIDE && height <= MAX_IMAGE_SIDE,
+        "a {width}x{height} image has more than {MAX_IMAGE_SIDE} columns or rows"
+    );
+    Ok(())
+}
+
+/// Everything that belongs to a single image: the buffers the passes work on and the
+/// bind groups that tie them to the shared [`CCLPipelines`].
 pub struct CCLState {
+    pipelines: Arc<CCLPipelines>,
     width: u32,
     height: u32,
-    init_pipeline: wgpu::ComputePipeline,
-    init_bind_group: wgpu::BindGroup,
-    compress_pipeline: wgpu::ComputePipeline,
-    compress_bind_group: wgpu::BindGroup,
-    merge_pipeline: wgpu::ComputePipeline,
-    merge_bind_group: wgpu::BindGroup,
-    final_labeling_pipeline: wgpu::ComputePipeline,
-    label_to_rgba_pipeline: wgpu::ComputePipeline,
-    label_to_rgba_bind_group: wgpu::BindGroup,
+    /// Number of pixels the labels and info buffers can hold.
+    capacity: u64,
+    /// The bound input, to bind it for stages that are enabled later.
+    input: Input,
+    dims_buffer: wgpu::Buffer,
+    foreground_buffer: wgpu::Buffer,
     labels_buffer: wgpu::Buffer,
+    info_buffer: wgpu::Buffer,
+    count_buffer: wgpu::Buffer,
+    bind_groups: BindGroups,
+    options: CCLOptions,
+    relabel: Option<Relabel>,
+    stats: Option<Stats>,
+    moments: Option<MomentSums>,
+    classes: Option<Classes>,
+    background: Option<Background>,
+    label_rgba: Option<LabelRgba>,
+    /// The texture supplied by the caller for [`CCLOptions::label_rgba`].
+    label_rgba_target: Option<wgpu::Texture>,
+    colorized: Option<Colorized>,
+    filter: Option<Filter>,
+    holes: Option<Holes>,
+    boundary: Option<Boundary>,
+}
+
+/// The init pass the [`BindGroups`] are created for.
+#[derive(Clone, Copy)]
+enum InitPass {
+    Input(InputFormat),
+    /// The layers of a [`BatchState`].
+    Batch,
+}
+
+impl InitPass {
+    fn pipelines(self, pipelines: &CCLPipelines) -> &InitPipelines {
+        match self {
+            InitPass::Input(format) => pipelines.init(format),
+            InitPass::Batch => pipelines.init_batch(),
+        }
+    }
+}
+
+struct BindGroups {
+    init_pass: InitPass,
+    init: wgpu::BindGroup,
+    compress: wgpu::BindGroup,
+    merge: wgpu::BindGroup,
+    count: wgpu::BindGroup,
 }
 
 impl CCLState {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, texture_bundle: &texture::TextureUInt) -> anyhow::Result<CCLState> {
-        let texture_size = texture_bundle.texture.size();
-        let width = texture_size.width;
-        let height = texture_size.height;
-        let dims = Dimensions {columns: width, rows: height, _pad0: 0, _pad1: 0};
+    /// Creates the pipelines and the state for one image. Prefer
+    /// [`CCLState::with_pipelines`] when more than one image is labeled.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, input: impl Into<Input>) -> anyhow::Result<CCLState> {
+        let pipelines = Arc::new(CCLPipelines::new(device));
+        Self::with_pipelines(device, queue, pipelines, input)
+    }
+
+    /// Creates the state for one image on top of already compiled pipelines. The input is
+    /// a [`texture::TextureUInt`] or any other [`Input`], e.g. a render target wrapped with
+    /// [`Input::texture_view`].
+    pub fn with_pipelines(device: &wgpu::Device, queue: &wgpu::Queue, pipelines: Arc<CCLPipelines>, input: impl Into<Input>) -> anyhow::Result<CCLState> {
+        let input = input.into();
+        check_size(input.size())?;
+        let (width, height) = input.size();
+        let dims = Dimensions {columns: width, rows: height, connectivity: Connectivity::default().as_u32(), pixel_nodes: 0};
         let dims_buffer = device.create_buffer_init(&BufferInitDescriptor{
             label: Some("Dimensions Uniform"),
             contents: bytemuck::cast_slice(&[dims]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let foreground_buffer = device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Foreground Uniform"),
+            contents: bytemuck::cast_slice(&[Foreground::default().to_params(None).with_channels(input.channels())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let num_pixels = width as u64 * height as u64;
-        // every pixel is now rgba<u8> so 32bit
-        let num_bytes_storage = num_pixels
-            .checked_mul(4)
-            .expect("The image was too big to create a storage buffer");
-        let labels_buffer = device.create_buffer(&BufferDescriptor{ 
-            label: Some("Labels Buffer"),
-            size: num_bytes_storage,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false, });
-        let info_buffer = device.create_buffer(&BufferDescriptor{ 
-            label: Some("Labels Buffer"),
-            size: num_bytes_storage,
+        let capacity = width as u64 * height as u64;
+        let (labels_buffer, info_buffer) = create_storage_buffers(device, queue, capacity);
+        let count_buffer = device.create_buffer(&BufferDescriptor{
+            label: Some("Component Count Buffer"),
+            size: 4,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false, });
+        let bind_groups = BindGroups::new(device, &pipelines, &input, &labels_buffer, &info_buffer, &dims_buffer, &foreground_buffer, &count_buffer);
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Clear Encoder"),
-        });
-        encoder.clear_buffer(&labels_buffer, 0, None);
-        queue.submit(std::iter::once(encoder.finish()));
-
-        let init_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("init_bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture { 
-                            access: wgpu::StorageTextureAccess::ReadOnly, 
-                            format: wgpu::TextureFormat::Rgba8Uint, 
-                            view_dimension: wgpu::TextureViewDimension::D2
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        Ok(Self {
+            pipelines,
+            width,
+            height,
+            capacity,
+            dims_buffer,
+            foreground_buffer,
+            labels_buffer,
+            info_buffer,
+            count_buffer,
+            bind_groups,
+            options: CCLOptions::default(),
+            relabel: None,
+            stats: None,
+            moments: None,
+            classes: None,
+            background: None,
+            label_rgba: None,
+            label_rgba_target: None,
+            colorized: None,
+            filter: None,
+            holes: None,
+            boundary: None,
+            input,
+        })
+    }
 
-        let compress_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("compress_bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            // 16 bytes is a safe minimum for two u32s + padding
-                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
-                        },
-                        count: None,
-                    },
-                ],
-            });
+    /// Binds a new image to this state.
+    ///
+    /// The labels and info buffers are only reallocated if the new image has more pixels
+    /// than they can hold, in which case they grow to the next power of two. Otherwise
+    /// only the dimensions uniform is updated and the bind groups are recreated.
+    pub fn set_input(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: impl Into<Input>) -> anyhow::Result<()> {
+        let input = input.into();
+        check_size(input.size())?;
+        if let Some(target) = &self.label_rgba_target {
+            label_rgba::check_target(target, input.size())?;
+        }
+        self.input = input;
+        (self.width, self.height) = self.input.size();
+        self.write_dims(queue);
+        self.write_foreground(queue);
+
+        let num_pixels = self.width as u64 * self.height as u64;
+        if num_pixels > self.capacity {
+            self.capacity = num_pixels.next_power_of_two();
+            (self.labels_buffer, self.info_buffer) = create_storage_buffers(device, queue, self.capacity);
+            // sized for the old capacity
+            self.relabel = None;
+        }
+        self.bind_groups = BindGroups::new(device, &self.pipelines, &self.input, &self.labels_buffer, &self.info_buffer, &self.dims_buffer, &self.foreground_buffer, &self.count_buffer);
+        if let Some(background) = self.background.as_mut() {
+            background.set_input(device, &self.pipelines, &self.input);
+        }
+        self.update_stages(device, queue);
 
-        let merge_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("merge_bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            // 16 bytes is a safe minimum for two u32s + padding
-                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        Ok(())
+    }
+
+    pub fn options(&self) -> CCLOptions {
+        self.options
+    }
+
+    /// Enables or disables the optional stages for the following calls to
+    /// [`CCLState::compute`].
+    pub fn set_options(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, options: CCLOptions) {
+        self.options = options;
+        self.write_dims(queue);
+        self.write_foreground(queue);
+        self.update_stages(device, queue);
+    }
+
+    fn write_dims(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[self.dims(self.options.connectivity)]));
+        if let Some(background) = &self.background {
+            background.write_dims(queue, self.dims(self.options.background_connectivity()));
+        }
+    }
+
+    fn write_foreground(&self, queue: &wgpu::Queue) {
+        let params = self.options.labeled_foreground().to_params(self.options.multi_value).with_channels(self.input.channels());
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[params]));
+        if let Some(background) = &self.background {
+            background.write_foreground(queue, self.options.foreground, self.options.multi_value, self.input.channels());
+        }
+    }
+
+    fn dims(&self, connectivity: Connectivity) -> Dimensions {
+        Dimensions {
+            columns: self.width,
+            rows: self.height,
+            connectivity: connectivity.as_u32(),
+            pixel_nodes: self.options.pixel_nodes(connectivity) as u32,
+        }
+    }
+
+    /// Creates the buffers of the enabled stages that do not exist yet and drops the ones of
+    /// disabled stages.
+    fn update_stages(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let num_pixels = self.width as u64 * self.height as u64;
+        if self.options.needs_relabel() {
+            let relabel = self.relabel.get_or_insert_with(|| Relabel::new(device, self.pipelines.relabel(), self.capacity));
+            relabel.set_len(queue, num_pixels);
+        } else {
+            self.relabel = None;
+        }
+
+        let max_components = self.max_components();
+        if self.options.needs_stats() {
+            if self.stats.as_ref().is_none_or(|stats| stats.capacity() < max_components) {
+                self.stats = Some(Stats::new(device, self.pipelines.stats(), max_components.next_power_of_two()));
+            }
+            if let Some(stats) = self.stats.as_mut() {
+                stats.set_len(max_components);
+                stats.set_accumulate(self.options.accumulates_stats());
+            }
+        } else {
+            self.stats = None;
+        }
+
+        if self.options.moments {
+            if self.moments.as_ref().is_none_or(|moments| moments.capacity() < max_components) {
+                self.moments = Some(MomentSums::new(device, self.pipelines.moments(), max_components.next_power_of_two()));
+            }
+            if let Some(moments) = self.moments.as_mut() {
+                moments.set_len(max_components);
+            }
+        } else {
+            self.moments = None;
+        }
+
+        match &self.stats {
+            Some(stats) if self.options.needs_filter() => {
+                let previous = self.filter.take();
+                let filters = Filters { clear_input: self.options.clear_input, ..self.options.filters() };
+                self.filter = Some(Filter::new(device, queue, self.pipelines.filter(), previous, (self.capacity, num_pixels), stats.buffer(), &self.count_buffer, &self.input, filters));
+            }
+            _ => self.filter = None,
+        }
+
+        if self.options.multi_value.is_some() {
+            if self.classes.as_ref().is_none_or(|classes| classes.capacity() < max_components) {
+                self.classes = Some(Classes::new(device, self.pipelines.classes(), max_components.next_power_of_two()));
+            }
+        } else {
+            self.classes = None;
+        }
+
+        if self.options.labels_background() {
+            if self.background.as_ref().is_none_or(|background| background.capacity() < self.capacity) {
+                let background = Background::new(device, queue, &self.pipelines, &self.input, self.capacity);
+                background.write_foreground(queue, self.options.foreground, self.options.multi_value, self.input.channels());
+                self.background = Some(background);
+            }
+            if let Some(background) = &self.background {
+                background.write_dims(queue, self.dims(self.options.background_connectivity()));
+            }
+            if let Some(background) = self.background.as_mut() {
+                if self.options.needs_relabel() {
+                    let relabel = background.relabel.get_or_insert_with(|| Relabel::new(device, self.pipelines.relabel(), self.capacity));
+                    relabel.set_len(queue, num_pixels);
+                } else {
+                    background.relabel = None;
+                }
+            }
+        } else {
+            self.background = None;
+        }
+
+        if self.options.fill_holes.is_some() {
+            if self.holes.as_ref().is_none_or(|holes| holes.mask().capacity() < self.capacity) {
+                self.holes = Some(Holes::new(device, self.pipelines.holes(), self.capacity));
+            }
+            if let Some(holes) = self.holes.as_mut() {
+                holes.mask_mut().set_len(num_pixels);
+            }
+        } else {
+            self.holes = None;
+        }
+
+        if self.options.contours {
+            if self.boundary.as_ref().is_none_or(|boundary| boundary.mask().capacity() < self.capacity) {
+                self.boundary = Some(Boundary::new(device, self.pipelines.boundary(), self.capacity));
+            }
+            if let Some(boundary) = self.boundary.as_mut() {
+                boundary.mask_mut().set_len(num_pixels);
+            }
+        } else {
+            self.boundary = None;
+        }
+
+        if self.options.label_rgba {
+            // the bind group has to be recreated for new labels buffers anyway
+            let size = self.size();
+            let texture = match (&self.label_rgba_target, &self.label_rgba) {
+                (Some(target), _) => Some(target),
+                (None, Some(label_rgba)) if label_rgba.fits(size) => Some(label_rgba.texture()),
+                (None, _) => None,
+            };
+            let layout = &self.pipelines.label_to_rgba_bind_group_layout;
+            self.label_rgba = Some(LabelRgba::new(device, layout, texture, size, &self.labels_buffer, &self.dims_buffer));
+        } else {
+            self.label_rgba = None;
+        }
+
+        if let Some(color_options) = self.options.colorize {
+            let previous = self.colorized.take();
+            self.colorized = Some(Colorized::new(device, queue, self.pipelines.colorize(), previous, self.size(), &self.labels_buffer, &self.dims_buffer, color_options));
+        } else {
+            self.colorized = None;
+        }
+    }
+
+    /// Makes [`CCLOptions::label_rgba`] write to `target` instead of a texture owned by the
+    /// state, or back to an owned one for `None`. The target has to be an Rgba8Uint texture
+    /// with storage binding usage at least as large as the bound image.
+    pub fn set_label_rgba_target(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, target: Option<wgpu::Texture>) -> anyhow::Result<()> {
+        if let Some(target) = &target {
+            label_rgba::check_target(target, self.size())?;
+        }
+        self.label_rgba_target = target;
+        // an owned texture can not be reused after a target was set
+        self.label_rgba = None;
+        self.update_stages(device, queue);
+        Ok(())
+    }
+
+    /// Upper bound for the number of components of the bound image. In binary mode with
+    /// 8-connectivity every 2x2 block is part of at most one component, with
+    /// 4-connectivity a checkerboard has a component for every other pixel. In multi-value
+    /// mode every pixel can be a component of its own.
+    fn max_components(&self) -> u64 {
+        let num_pixels = self.width as u64 * self.height as u64;
+        match (self.options.multi_value, self.options.connectivity) {
+            (Some(_), _) => num_pixels,
+            (None, Connectivity::Four) => num_pixels.div_ceil(2),
+            (None, Connectivity::Eight) => self.width.div_ceil(2) as u64 * self.height.div_ceil(2) as u64,
+        }
+    }
+
+    /// Width and height of the currently bound image.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Number of pixels the state can label without reallocating its buffers.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// The pipelines this state dispatches, to create further states from.
+    pub fn pipelines(&self) -> &Arc<CCLPipelines> {
+        &self.pipelines
+    }
+
+    /// Number of workgroups for the passes that work on 2x2 blocks with 8x8 threads.
+    fn block_workgroups(&self) -> (u32, u32) {
+        (self.width.div_ceil(16), self.height.div_ceil(16))
+    }
 
-        let label_to_rgba_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("merge_bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None, 
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba8Uint,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            // 16 bytes is a safe minimum for two u32s + padding
-                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
-                        },
-                        count: None,
-                    },
-                ],
+    /// Records all passes into `encoder`. This can be called as often as needed, every call
+    /// labels the currently bound image from scratch. With [`CCLOptions::clear_input`] the
+    /// last pass removes the filtered components from the input, so the next call labels
+    /// the cleared image.
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) -> Result<CCLOutput, wgpu::SurfaceError> {
+        let pipelines = &self.pipelines;
+        let (blocks_x, blocks_y) = self.block_workgroups();
+        encoder.clear_buffer(&self.count_buffer, 0, None);
+        if let Some(background) = &self.background {
+            encoder.clear_buffer(&background.count_buffer, 0, None);
+        }
+        if let Some(stats) = &self.stats {
+            stats.clear(encoder);
+        }
+        if let Some(moments) = &self.moments {
+            moments.clear(encoder);
+        }
+        if let Some(filter) = &self.filter {
+            filter.clear(encoder);
+        }
+        if let Some(holes) = &self.holes {
+            holes.clear(encoder);
+        }
+        if let Some(boundary) = &self.boundary {
+            boundary.mask().clear(encoder);
+        }
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                timestamp_writes: None,
             });
 
-        let init_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            self.bind_groups.dispatch_labeling(&mut compute_pass, pipelines, (blocks_x, blocks_y), 1);
+
+            if let Some(relabel) = &self.relabel {
+                relabel.dispatch(&mut compute_pass, pipelines.relabel(), &self.bind_groups.merge, (blocks_x, blocks_y));
+            }
+
+            if let Some(stats) = &self.stats {
+                stats.dispatch(&mut compute_pass, pipelines.stats(), &self.bind_groups.merge, (blocks_x, blocks_y), self.size());
+            }
+
+            if let Some(moments) = &self.moments {
+                moments.dispatch(&mut compute_pass, pipelines.moments(), &self.bind_groups.merge, (blocks_x, blocks_y));
+            }
+
+            if let Some(classes) = &self.classes {
+                classes.dispatch(&mut compute_pass, pipelines.store_classes(self.input.format()), &self.bind_groups.init, (blocks_x, blocks_y));
+            }
+
+            if let Some(filter) = &self.filter {
+                filter.dispatch(&mut compute_pass, pipelines.filter(), &self.bind_groups.merge, (blocks_x, blocks_y), (self.width.div_ceil(8), self.height.div_ceil(8)));
+            }
+
+            if let Some(boundary) = &self.boundary {
+                boundary.dispatch(&mut compute_pass, pipelines.boundary(), &self.bind_groups.merge, self.size());
+            }
+
+            if let Some(background) = &self.background {
+                background.bind_groups.dispatch_labeling(&mut compute_pass, pipelines, (blocks_x, blocks_y), 1);
+                if let Some(relabel) = &background.relabel {
+                    relabel.dispatch(&mut compute_pass, pipelines.relabel(), &background.bind_groups.merge, (blocks_x, blocks_y));
+                }
+                if let Some(holes) = &self.holes {
+                    holes.dispatch(&mut compute_pass, pipelines.holes(), &background.bind_groups.merge, self.size());
+                }
+            }
+
+            if let Some(label_rgba) = &self.label_rgba {
+                compute_pass.set_pipeline(&pipelines.label_to_rgba_pipeline);
+                compute_pass.set_bind_group(0, label_rgba.bind_group(), &[]);
+                compute_pass.dispatch_workgroups(
+                    self.width.div_ceil(8),
+                    self.height.div_ceil(8),
+                    1
+                );
+            }
+
+            if let Some(colorized) = &self.colorized {
+                colorized.dispatch(&mut compute_pass, pipelines.colorize());
+            }
+
+            if let Some(filter) = &self.filter {
+                filter.dispatch_clear_input(&mut compute_pass, pipelines.filter(), &self.bind_groups.merge, (self.width.div_ceil(8), self.height.div_ceil(8)));
+            }
+        }
+
+        Ok(CCLOutput {
+            labels: self.labels_buffer.clone(),
+            component_count: self.count_buffer.clone(),
+            background_labels: self.background.as_ref().map(|background| background.labels_buffer.clone()),
+            background_count: self.background.as_ref().map(|background| background.count_buffer.clone()),
+            label_rgba: self.label_rgba.as_ref().map(|label_rgba| label_rgba.texture().clone()),
+            colorized: self.colorized.as_ref().map(|colorized| colorized.texture().clone()),
+            mask: self.filter.as_ref().map(|filter| filter.mask().buffer().clone()),
+            filled: self.holes.as_ref().map(|holes| holes.mask().buffer().clone()),
+            boundary: self.boundary.as_ref().map(|boundary| boundary.mask().buffer().clone()),
+        })
+    }
+
+    /// Reads the labels of the bound image back to the CPU. The work recorded by
+    /// [`CCLState::compute`] has to be submitted before.
+    pub async fn read_labels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<LabelMap> {
+        let num_pixels = self.width as u64 * self.height as u64;
+        let labels = readback::read_buffer::<u32>(device, queue, &self.labels_buffer, num_pixels).await?;
+        Ok(LabelMap::new(self.width, self.height, labels))
+    }
+
+    /// Reads the labels of the background back to the CPU, which are only computed with
+    /// [`Phase::Both`] or [`CCLOptions::fill_holes`]. The work recorded by
+    /// [`CCLState::compute`] has to be submitted before.
+    pub async fn read_background_labels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<LabelMap> {
+        let Some(background) = &self.background else {
+            anyhow::bail!("the background is only labeled with Phase::Both or fill_holes");
+        };
+        let num_pixels = self.width as u64 * self.height as u64;
+        let labels = readback::read_buffer::<u32>(device, queue, &background.labels_buffer, num_pixels).await?;
+        Ok(LabelMap::new(self.width, self.height, labels))
+    }
+
+    /// Reads the number of background components back to the CPU, see
+    /// [`CCLState::read_background_labels`].
+    pub async fn read_background_count(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<u32> {
+        let Some(background) = &self.background else {
+            anyhow::bail!("the background is only labeled with Phase::Both or fill_holes");
+        };
+        let count = readback::read_buffer::<u32>(device, queue, &background.count_buffer, 1).await?;
+        Ok(count[0])
+    }
+
+    /// Reads the number of components back to the CPU, which only copies 4 bytes. The work
+    /// recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_component_count(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<u32> {
+        let count = readback::read_buffer::<u32>(device, queue, &self.count_buffer, 1).await?;
+        Ok(count[0])
+    }
+
+    /// Reads the statistics of all components back to the CPU, ordered by label. The
+    /// `stats` stage has to be enabled in the [`CCLOptions`] and the work recorded by
+    /// [`CCLState::compute`] has to be submitted before.
+    pub async fn read_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<ComponentStats>> {
+        let Some(stats) = self.stats.as_ref().filter(|stats| stats.accumulates()) else {
+            anyhow::bail!("the stats stage is not enabled");
+        };
+        let count = self.read_component_count(device, queue).await? as u64;
+        let raw = readback::read_buffer::<RawStats>(device, queue, stats.buffer(), count).await?;
+        let mut raw_moments = match &self.moments {
+            Some(moments) => Some(readback::read_buffer::<RawMoments>(device, queue, moments.buffer(), count).await?.into_iter()),
+            None => None,
+        };
+        Ok(raw
+            .into_iter()
+            .zip(1..)
+            .map(|(raw, label)| raw.to_stats(label, raw_moments.as_mut().and_then(|moments| moments.next())))
+            .collect())
+    }
+
+    /// Reads the value of every component back to the CPU, the entry at index `i` belongs
+    /// to label `i + 1`. Multi-value mode has to be enabled in the [`CCLOptions`] and the
+    /// work recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_classes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<u32>> {
+        let Some(classes) = &self.classes else {
+            anyhow::bail!("multi-value mode is not enabled");
+        };
+        let count = self.read_component_count(device, queue).await? as u64;
+        readback::read_buffer::<u32>(device, queue, classes.buffer(), count).await
+    }
+
+    /// Reads the mask of the components that passed the filters back to the CPU, see
+    /// [`CCLOutput::mask`]. One of the filters of [`CCLOutput::mask`] has to be set and the
+    /// work recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_mask(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<bool>> {
+        let Some(filter) = &self.filter else {
+            anyhow::bail!("no filter is enabled");
+        };
+        filter.mask().read_bits(device, queue).await
+    }
+
+    /// Reads the foreground with its holes filled back to the CPU, see
+    /// [`CCLOutput::filled`]. [`CCLOptions::fill_holes`] has to be set and the work
+    /// recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_filled(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<bool>> {
+        let Some(holes) = &self.holes else {
+            anyhow::bail!("fill_holes is not enabled");
+        };
+        holes.mask().read_bits(device, queue).await
+    }
+
+    /// Reads the boundary pixels of the components back to the CPU, see
+    /// [`CCLOutput::boundary`]. [`CCLOptions::contours`] has to be set and the work
+    /// recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_boundary(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<bool>> {
+        let Some(boundary) = &self.boundary else {
+            anyhow::bail!("contours is not enabled");
+        };
+        boundary.mask().read_bits(device, queue).await
+    }
+
+    /// Reads the labels and the boundary pixels back to the CPU and traces the outer
+    /// contour of every component along them, keyed by label. See
+    /// [`CCLState::read_boundary`].
+    pub async fn read_contours(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<BTreeMap<u32, Contour>> {
+        let boundary = self.read_boundary(device, queue).await?;
+        let labels = self.read_labels(device, queue).await?;
+        Ok(contours::trace_contours(&labels, &boundary))
+    }
+
+    /// Reads the mask of the components that passed the filters and the labels, in which
+    /// the removed components are background, back to the CPU. See
+    /// [`CCLState::read_mask`].
+    pub async fn read_filtered(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<(Vec<bool>, LabelMap)> {
+        let mask = self.read_mask(device, queue).await?;
+        let labels = self.read_labels(device, queue).await?;
+        Ok((mask, labels))
+    }
+
+    /// Reads the colorized labels back to the CPU. [`CCLOptions::colorize`] has to be set
+    /// and the work recorded by [`CCLState::compute`] has to be submitted before.
+    pub async fn read_colorized(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<image::RgbaImage> {
+        let Some(colorized) = &self.colorized else {
+            anyhow::bail!("colorize is not enabled");
+        };
+        let data = readback::read_texture(device, queue, colorized.texture(), 4).await?;
+        image::RgbaImage::from_raw(self.width, self.height, data)
+            .ok_or_else(|| anyhow::anyhow!("the colorized texture does not match the image size"))
+    }
+
+    /// Reads the colorized labels back and saves them as PNG, see
+    /// [`CCLState::read_colorized`].
+    pub async fn save_colorized(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let img = self.read_colorized(device, queue).await?;
+        img.save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+/// Creates the labels and info buffers for `capacity` pixels.
+fn create_storage_buffers(device: &wgpu::Device, queue: &wgpu::Queue, capacity: u64) -> (wgpu::Buffer, wgpu::Buffer) {
+    // one u32 per pixel
+    let num_bytes_storage = capacity
+        .checked_mul(4)
+        .expect("The image was too big to create a storage buffer");
+    let labels_buffer = device.create_buffer(&BufferDescriptor{
+        label: Some("Labels Buffer"),
+        size: num_bytes_storage,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false, });
+    let info_buffer = device.create_buffer(&BufferDescriptor{
+        label: Some("Info Buffer"),
+        size: num_bytes_storage,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false, });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Clear Encoder"),
+    });
+    encoder.clear_buffer(&labels_buffer, 0, None);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    (labels_buffer, info_buffer)
+}
+
+impl BindGroups {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        device: &wgpu::Device,
+        pipelines: &CCLPipelines,
+        input: &Input,
+        labels_buffer: &wgpu::Buffer,
+        info_buffer: &wgpu::Buffer,
+        dims_buffer: &wgpu::Buffer,
+        foreground_buffer: &wgpu::Buffer,
+        count_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let init_pass = InitPass::Input(input.format());
+        Self::with_init_pass(device, pipelines, init_pass, input.binding_resource(), labels_buffer, info_buffer, dims_buffer, foreground_buffer, count_buffer)
+    }
+
+    /// `input` is bound to binding 0 of the init pass, `count_buffer` holds one count per
+    /// layer of a batch.
+    #[allow(clippy::too_many_arguments)]
+    fn with_init_pass(
+        device: &wgpu::Device,
+        pipelines: &CCLPipelines,
+        init_pass: InitPass,
+        input: wgpu::BindingResource,
+        labels_buffer: &wgpu::Buffer,
+        info_buffer: &wgpu::Buffer,
+        dims_buffer: &wgpu::Buffer,
+        foreground_buffer: &wgpu::Buffer,
+        count_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let init = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("init_bind_group"),
-            layout: &init_bind_group_layout,
+            layout: &init_pass.pipelines(pipelines).bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_bundle.view)
+                    resource: input,
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
@@ -217,28 +904,36 @@ impl CCLState {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
+                    resource: dims_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
                     resource: info_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: foreground_buffer.as_entire_binding(),
+                },
             ],
         });
 
-        let compress_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &compress_bind_group_layout,
+        let compress = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.compress_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: labels_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
-                    binding: 1,
+                    binding: 2,
                     resource: dims_buffer.as_entire_binding(),
                 },
             ],
             label: Some("compress_bind_group"),
         });
-        
-        let merge_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &merge_bind_group_layout,
+
+        let merge = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.merge_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -256,215 +951,53 @@ impl CCLState {
             label: Some("merge_bind_group"),
         });
 
-        let label_to_rgba_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &label_to_rgba_bind_group_layout,
+        let count = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.count_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: labels_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_bundle.view)
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: dims_buffer.as_entire_binding(),
+                    resource: count_buffer.as_entire_binding(),
                 },
             ],
-            label: Some("label_to_rgba_bind_group"),
-        });
-
-
-        let shader_string = include_wesl!("init_labeling");
-        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
-
-        let init_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Init Shader"),
-            source: shader_source,
-        });
-
-        let shader_string = include_wesl!("compress");
-        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
-
-        let compress_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compress Shader"),
-            source: shader_source,
-        });
-
-        let shader_string = include_wesl!("merge");
-        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
-
-        let merge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Merge Shader"),
-            source: shader_source,
+            label: Some("count_bind_group"),
         });
 
-        let shader_string = include_wesl!("final_labeling");
-        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
-
-        let final_labeling_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Final Labeling Shader"),
-            source: shader_source,
-        });
-
-        let shader_string = include_wesl!("label_to_rgba");
-        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
-
-        let label_to_rgba_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Label to RGBA Shader"),
-            source: shader_source,
-        });
-
-        let init_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Init pipeline layout"),
-                bind_group_layouts: &[ &init_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let compress_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("compress pipeline layout"),
-                bind_group_layouts: &[ &compress_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let merge_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("merge pipeline layout"),
-                bind_group_layouts: &[ &merge_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let label_to_rgba_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("label to rgba pipeline layout"),
-                bind_group_layouts: &[ &label_to_rgba_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let init_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("init Pipeline"),
-            layout: Some(&init_pipeline_layout),
-            module: &init_shader,
-            entry_point: "init_labeling".into(),
-            compilation_options: Default::default(),
-            cache: Default::default(),
-        });
-
-        let compress_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Compress Pipeline"),
-            layout: Some(&compress_pipeline_layout),
-            module: &compress_shader,
-            entry_point: "compress".into(),
-            compilation_options: Default::default(),
-            cache: Default::default(),
-        });
-
-        let merge_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("merge Pipeline"),
-            layout: Some(&merge_pipeline_layout),
-            module: &merge_shader,
-            entry_point: "merge".into(),
-            compilation_options: Default::default(),
-            cache: Default::default(),
-        });
-
-        let final_labeling_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("final labeling Pipeline"),
-            layout: Some(&merge_pipeline_layout),
-            module: &final_labeling_shader,
-            entry_point: "final_labeling".into(),
-            compilation_options: Default::default(),
-            cache: Default::default(),
-        });
-
-        let label_to_rgba_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Label to RGBA Pipeline"),
-            layout: Some(&label_to_rgba_pipeline_layout),
-            module: &label_to_rgba_shader,
-            entry_point: "label_to_rgba".into(),
-            compilation_options: Default::default(),
-            cache: Default::default(),
-        });
-
-
-
-
-        Ok(Self {
-            width,
-            height,
-            init_pipeline,
-            init_bind_group,
-            compress_pipeline,
-            compress_bind_group,
-            merge_pipeline,
-            merge_bind_group,
-            final_labeling_pipeline,
-            label_to_rgba_pipeline,
-            label_to_rgba_bind_group,
-            labels_buffer,
-        })
-    }
-
-    pub fn compute(self, encoder: &mut wgpu::CommandEncoder) -> Result<wgpu::Buffer, wgpu::SurfaceError> {
-        // this needs to run every frame, otherwise the buffer will be cleared
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compute Pass"),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(&self.init_pipeline);
-            compute_pass.set_bind_group(0, &self.init_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                // workgroup / 2 because it is checking 2x2 blocks
-                self.width/16 + self.width % 16, 
-                self.height/16 + self.height % 16, 
-                1
-            );
-
-            compute_pass.set_pipeline(&self.compress_pipeline);
-            compute_pass.set_bind_group(0, &self.compress_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                self.width/16 + self.width % 16, 
-                self.height/16 + self.height % 16, 
-                1
-            );
-
-            compute_pass.set_pipeline(&self.merge_pipeline);
-            compute_pass.set_bind_group(0, &self.merge_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                self.width/16 + self.width % 16, 
-                self.height/16 + self.height % 16, 
-                1
-            );
-
-            compute_pass.set_pipeline(&self.compress_pipeline);
-            compute_pass.set_bind_group(0, &self.compress_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                self.width/16 + self.width % 16, 
-                self.height/16 + self.height % 16, 
-                1
-            );
-            compute_pass.set_pipeline(&self.final_labeling_pipeline);
-            compute_pass.set_bind_group(0, &self.merge_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                self.width/16 + self.width % 16, 
-                self.height/16 + self.height % 16, 
-                1
-            );
-            compute_pass.set_pipeline(&self.label_to_rgba_pipeline);
-            compute_pass.set_bind_group(0, &self.label_to_rgba_bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                self.width/8 + self.width % 8, 
-                self.height/8 + self.width % 8, 
-                1
-            );
+        Self {
+            init_pass,
+            init,
+            compress,
+            merge,
+            count,
         }
+    }
 
-        Ok(self.labels_buffer)
+    /// Records the labeling passes and the count, which has to see the labels before they
+    /// are relabeled. Every pass covers `layers` images, one per workgroup in z.
+    fn dispatch_labeling(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &CCLPipelines, (blocks_x, blocks_y): (u32, u32), layers: u32) {
+        compute_pass.set_pipeline(&self.init_pass.pipelines(pipelines).pipeline);
+        compute_pass.set_bind_group(0, &self.init, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
+
+        compute_pass.set_pipeline(&pipelines.compress_pipeline);
+        compute_pass.set_bind_group(0, &self.compress, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
+
+        compute_pass.set_pipeline(&pipelines.merge_pipeline);
+        compute_pass.set_bind_group(0, &self.merge, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
+
+        compute_pass.set_pipeline(&pipelines.compress_pipeline);
+        compute_pass.set_bind_group(0, &self.compress, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
+
+        compute_pass.set_pipeline(&pipelines.final_labeling_pipeline);
+        compute_pass.set_bind_group(0, &self.merge, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
+
+        // has to see the labels before they are relabeled
+        compute_pass.set_pipeline(&pipelines.count_pipeline);
+        compute_pass.set_bind_group(0, &self.merge, &[]);
+        compute_pass.set_bind_group(1, &self.count, &[]);
+        compute_pass.dispatch_workgroups(blocks_x, blocks_y, layers);
     }
 }
-
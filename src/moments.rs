@@ -0,0 +1,283 @@
+use wesl::include_wesl;
+
+/// Second order central moments of a component and the shape measures derived from them,
+/// following the conventions of scikit-image's `regionprops`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Moments {
+    /// Sum of (x - x̄)² over the pixels of the component.
+    pub mu20: f64,
+    /// Sum of (y - ȳ)².
+    pub mu02: f64,
+    /// Sum of (x - x̄)(y - ȳ).
+    pub mu11: f64,
+    /// Length of the major axis of the ellipse with the same second moments.
+    pub major_axis_length: f64,
+    pub minor_axis_length: f64,
+    /// Angle between the y axis (the rows) and the major axis in radians, between -pi/2
+    /// and pi/2, counting counter-clockwise.
+    pub orientation: f64,
+    /// Ratio of the focal distance to the major axis length of that ellipse, 0 for a
+    /// circle and approaching 1 for a line.
+    pub eccentricity: f64,
+}
+
+impl Moments {
+    /// Computes the central moments from the raw sums. The products are computed exactly
+    /// before dividing, the raw sums of big components are far beyond the precision of f64.
+    fn from_raw_sums(area: u64, sum_x: u64, sum_y: u64, sum_xx: u64, sum_yy: u64, sum_xy: u64) -> Moments {
+        let n = area.max(1) as i128;
+        let central = |sum_ab: u64, sum_a: u64, sum_b: u64| {
+            (n * sum_ab as i128 - sum_a as i128 * sum_b as i128) as f64 / n as f64
+        };
+        let mu20 = central(sum_xx, sum_x, sum_x);
+        let mu02 = central(sum_yy, sum_y, sum_y);
+        let mu11 = central(sum_xy, sum_x, sum_y);
+
+        // eigenvalues of the inertia tensor [[mu20, -mu11], [-mu11, mu02]] / area
+        let area = n as f64;
+        let (a, b, c) = (mu20 / area, -mu11 / area, mu02 / area);
+        let mean = (a + c) / 2.0;
+        let spread = (((a - c) / 2.0).powi(2) + b * b).sqrt();
+        let l1 = mean + spread;
+        let l2 = (mean - spread).max(0.0);
+
+        let eccentricity = if l1 == 0.0 { 0.0 } else { (1.0 - l2 / l1).sqrt() };
+        // atan2 gives 0 or pi/2 for a == c, regionprops takes the diagonal the sign of b
+        // points to instead
+        let orientation = if a == c {
+            if b < 0.0 { -std::f64::consts::FRAC_PI_4 } else { std::f64::consts::FRAC_PI_4 }
+        } else {
+            0.5 * (-2.0 * b).atan2(c - a)
+        };
+
+        Moments {
+            mu20,
+            mu02,
+            mu11,
+            major_axis_length: 4.0 * l1.sqrt(),
+            minor_axis_length: 4.0 * l2.sqrt(),
+            orientation,
+            eccentricity,
+        }
+    }
+}
+
+/// One `ComponentMoments` struct of `moments.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct RawMoments {
+    sum_xx_lo: u32,
+    sum_xx_hi: u32,
+    sum_yy_lo: u32,
+    sum_yy_hi: u32,
+    sum_xy_lo: u32,
+    sum_xy_hi: u32,
+}
+
+impl RawMoments {
+    /// `sum_x` and `sum_y` are the coordinate sums accumulated by the stats pass.
+    pub(crate) fn to_moments(self, area: u32, sum_x: u64, sum_y: u64) -> Moments {
+        let wide = |lo: u32, hi: u32| (hi as u64) << 32 | lo as u64;
+        Moments::from_raw_sums(
+            area as u64,
+            sum_x,
+            sum_y,
+            wide(self.sum_xx_lo, self.sum_xx_hi),
+            wide(self.sum_yy_lo, self.sum_yy_hi),
+            wide(self.sum_xy_lo, self.sum_xy_hi),
+        )
+    }
+}
+
+pub(crate) struct MomentsPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    accumulate_pipeline: wgpu::ComputePipeline,
+}
+
+impl MomentsPipelines {
+    /// `merge_bind_group_layout` is bound to group 0, the sums are accumulated per pixel
+    /// from the final labels.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> MomentsPipelines {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("moments_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader_string = include_wesl!("moments");
+        let moments_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Moments Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("moments pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let accumulate_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Accumulate Moments Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &moments_shader,
+            entry_point: "accumulate_moments".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            accumulate_pipeline,
+        }
+    }
+}
+
+/// The per-image moment sums, one entry per possible component.
+pub(crate) struct MomentSums {
+    sums_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Number of components the buffer can hold.
+    capacity: u64,
+    /// Number of entries that are cleared before every run.
+    len: u64,
+}
+
+impl MomentSums {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &MomentsPipelines, capacity: u64) -> MomentSums {
+        let sums_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Moments Buffer"),
+            size: capacity * std::mem::size_of::<RawMoments>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("moments_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sums_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            sums_buffer,
+            bind_group,
+            capacity,
+            len: capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Sets the maximum number of components of the bound image.
+    pub(crate) fn set_len(&mut self, max_components: u64) {
+        self.len = max_components.min(self.capacity);
+    }
+
+    pub(crate) fn buffer(&self) -> &wgpu::Buffer {
+        &self.sums_buffer
+    }
+
+    /// Clears the entries of the bound image. Has to be recorded outside of a compute pass.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.len * std::mem::size_of::<RawMoments>() as u64;
+        encoder.clear_buffer(&self.sums_buffer, 0, Some(size));
+    }
+
+    /// Records the accumulation, which has to run after the relabel stage.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &MomentsPipelines, merge_bind_group: &wgpu::BindGroup, workgroups: (u32, u32)) {
+        compute_pass.set_pipeline(&pipelines.accumulate_pipeline);
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use super::Moments;
+
+    fn moments(pixels: &[(u64, u64)]) -> Moments {
+        let sum = |f: &dyn Fn(u64, u64) -> u64| pixels.iter().map(|&(x, y)| f(x, y)).sum();
+        Moments::from_raw_sums(pixels.len() as u64, sum(&|x, _| x), sum(&|_, y| y), sum(&|x, _| x * x), sum(&|_, y| y * y), sum(&|x, y| x * y))
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn rectangle() {
+        // 4 columns and 2 rows: variances 1.25 and 0.25 per pixel
+        let pixels: Vec<_> = (0..2).flat_map(|y| (0..4).map(move |x| (x, y))).collect();
+        let m = moments(&pixels);
+        assert_close(m.mu20, 10.0);
+        assert_close(m.mu02, 2.0);
+        assert_close(m.mu11, 0.0);
+        assert_close(m.major_axis_length, 4.0 * 1.25f64.sqrt());
+        assert_close(m.minor_axis_length, 2.0);
+        // the major axis runs along the columns
+        assert_close(m.orientation.abs(), FRAC_PI_2);
+        assert_close(m.eccentricity, 0.8f64.sqrt());
+    }
+
+    #[test]
+    fn diagonal_line() {
+        // all central moments are the sum of (i - 4.5)^2 = 82.5
+        let falling: Vec<_> = (0..10).map(|i| (i, i)).collect();
+        let m = moments(&falling);
+        assert_close(m.mu20, 82.5);
+        assert_close(m.mu11, 82.5);
+        assert_close(m.major_axis_length, 4.0 * 16.5f64.sqrt());
+        assert_close(m.minor_axis_length, 0.0);
+        assert_close(m.eccentricity, 1.0);
+        assert_close(m.orientation, -FRAC_PI_4);
+
+        let rising: Vec<_> = (0..10).map(|i| (i, 9 - i)).collect();
+        let m = moments(&rising);
+        assert_close(m.mu11, -82.5);
+        assert_close(m.eccentricity, 1.0);
+        assert_close(m.orientation, FRAC_PI_4);
+    }
+
+    #[test]
+    fn square() {
+        let pixels: Vec<_> = (0..3).flat_map(|y| (0..3).map(move |x| (x, y))).collect();
+        let m = moments(&pixels);
+        assert_close(m.mu20, 6.0);
+        assert_close(m.mu02, 6.0);
+        assert_close(m.major_axis_length, 4.0 * (6.0f64 / 9.0).sqrt());
+        assert_close(m.minor_axis_length, m.major_axis_length);
+        assert_close(m.eccentricity, 0.0);
+        // a == c and b == 0
+        assert_close(m.orientation, FRAC_PI_4);
+    }
+
+    #[test]
+    fn single_pixel() {
+        let m = moments(&[(7, 3)]);
+        assert_close(m.mu20, 0.0);
+        assert_close(m.major_axis_length, 0.0);
+        assert_close(m.minor_axis_length, 0.0);
+        assert_close(m.eccentricity, 0.0);
+        assert_close(m.orientation, FRAC_PI_4);
+    }
+}
@@ -0,0 +1,305 @@
+use wesl::include_wesl;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{CCLOutput, readback};
+
+/// How [`OverlayRenderer`] draws the components over the source image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayOptions {
+    /// How much of the component color is mixed into the source, from 0 (only the source)
+    /// to 1 (only the component color).
+    pub opacity: f32,
+    /// Draws the pixels at the boundary of every component in this color, mixed in by its
+    /// alpha. `None` draws the boundaries like the rest of the component.
+    pub boundary_color: Option<[f32; 4]>,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        Self {
+            opacity: 0.5,
+            boundary_color: Some([1.0, 1.0, 1.0, 1.0]),
+        }
+    }
+}
+
+/// The `OverlayParams` uniform of `render.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayParams {
+    columns: u32,
+    rows: u32,
+    opacity: f32,
+    boundaries: u32,
+    boundary_color: [f32; 4],
+}
+
+/// The format of the overlay textures.
+pub const OVERLAY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Draws the colorized labels of a [`crate::CCLState`] over the image they were computed
+/// from, with the fullscreen triangle of `render.wesl`. Needs no surface, the result is
+/// rendered into a texture and can be read back or saved as PNG.
+pub struct OverlayRenderer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl OverlayRenderer {
+    pub fn new(device: &wgpu::Device) -> OverlayRenderer {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("overlay_bind_group_layout"),
+                entries: &[
+                    // the source image and the colorized labels
+                    texture_entry(2),
+                    texture_entry(3),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(std::num::NonZeroU64::new(std::mem::size_of::<OverlayParams>() as u64).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader_string = include_wesl!("render");
+        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Render Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("overlay pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &render_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &render_shader,
+                entry_point: Some("fs_overlay"),
+                // the shader blends, the target is overwritten
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: OVERLAY_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Records the overlay of `output` over `source` into `encoder`, drawn into `target`.
+    ///
+    /// `output` needs [`CCLOutput::colorized`], see [`crate::CCLOptions::colorize`].
+    /// `source` is a view of a float texture the size of the labeled image, e.g. one
+    /// created with [`upload_source`], and `target` a view of an [`OVERLAY_FORMAT`]
+    /// texture with render attachment usage of the same size.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        output: &CCLOutput,
+        target: &wgpu::TextureView,
+        options: OverlayOptions,
+    ) -> anyhow::Result<()> {
+        let Some(colorized) = &output.colorized else {
+            anyhow::bail!("the overlay needs the colorized labels, enable colorize in the options");
+        };
+        let (width, height) = (colorized.width(), colorized.height());
+        for (name, texture) in [("source", source.texture()), ("target", target.texture())] {
+            anyhow::ensure!(
+                texture.width() == width && texture.height() == height,
+                "the {name} texture is {}x{}, the labeled image {width}x{height}",
+                texture.width(),
+                texture.height(),
+            );
+        }
+
+        let params = OverlayParams {
+            columns: width,
+            rows: height,
+            opacity: options.opacity,
+            boundaries: options.boundary_color.is_some() as u32,
+            boundary_color: options.boundary_color.unwrap_or_default(),
+        };
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Overlay Params Uniform"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let colors = colorized.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&colors),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: output.labels.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("overlay_bind_group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+
+    /// Renders the overlay into a new texture and reads it back. The labeling of `output`
+    /// has to be submitted before or recorded into the same queue.
+    pub async fn render_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::TextureView,
+        output: &CCLOutput,
+        options: OverlayOptions,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let (width, height) = (source.texture().width(), source.texture().height());
+        let target = create_target(device, width, height);
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Overlay Encoder"),
+        });
+        self.render(device, &mut encoder, source, output, &target_view, options)?;
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let data = readback::read_texture(device, queue, &target, 4).await?;
+        image::RgbaImage::from_raw(width, height, data)
+            .ok_or_else(|| anyhow::anyhow!("the overlay texture does not match the image size"))
+    }
+
+    /// Renders the overlay and saves it as PNG, see [`OverlayRenderer::render_image`].
+    pub async fn save_png(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::TextureView,
+        output: &CCLOutput,
+        options: OverlayOptions,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<()> {
+        let img = self.render_image(device, queue, source, output, options).await?;
+        img.save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+/// Creates an [`OVERLAY_FORMAT`] texture to render the overlay into, which can be sampled
+/// and copied from as well.
+pub fn create_target(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Overlay Texture"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OVERLAY_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Uploads `img` as the source of an overlay. The labeling itself reads an Rgba8Uint
+/// texture, which can not be sampled as float.
+pub fn upload_source(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage) -> wgpu::Texture {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Overlay Source Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        &rgba,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+    texture
+}
@@ -0,0 +1,353 @@
+use std::sync::OnceLock;
+
+use wesl::include_wesl;
+
+use crate::moments::MomentsPipelines;
+use crate::classes::ClassesPipelines;
+use crate::colorize::ColorizePipelines;
+use crate::filter::FilterPipelines;
+use crate::holes::HolesPipelines;
+use crate::contours::BoundaryPipelines;
+use crate::relabel::RelabelPipelines;
+use crate::stats::StatsPipelines;
+use crate::input::{InitPipelines, InputFormat};
+
+/// The bind group layouts and compute pipelines of all BKE passes.
+///
+/// Compiling the shaders and creating the pipelines dominates the setup time of a
+/// `CCLState`, while none of it depends on the image. Create this once per device and
+/// share it (it is handed around as an `Arc`) between all states that label images,
+/// [`crate::BatchState`]s included.
+///
+/// Only the passes every labeling runs are compiled up front. The init pass of an
+/// [`InputFormat`] and the optional stages are compiled the first time a state uses them.
+pub struct CCLPipelines {
+    device: wgpu::Device,
+    /// The init pass of every [`InputFormat`], indexed by [`InputFormat::index`].
+    init: [OnceLock<InitPipelines>; InputFormat::ALL.len()],
+    /// The init pass of the layers of a [`crate::BatchState`].
+    init_batch: OnceLock<InitPipelines>,
+    pub(crate) compress_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) merge_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) label_to_rgba_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) count_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) compress_pipeline: wgpu::ComputePipeline,
+    pub(crate) merge_pipeline: wgpu::ComputePipeline,
+    pub(crate) final_labeling_pipeline: wgpu::ComputePipeline,
+    pub(crate) label_to_rgba_pipeline: wgpu::ComputePipeline,
+    pub(crate) count_pipeline: wgpu::ComputePipeline,
+    relabel: OnceLock<RelabelPipelines>,
+    stats: OnceLock<StatsPipelines>,
+    moments: OnceLock<MomentsPipelines>,
+    classes: OnceLock<ClassesPipelines>,
+    colorize: OnceLock<ColorizePipelines>,
+    filter: OnceLock<FilterPipelines>,
+    holes: OnceLock<HolesPipelines>,
+    boundary: OnceLock<BoundaryPipelines>,
+}
+
+impl CCLPipelines {
+    pub(crate) fn init(&self, format: InputFormat) -> &InitPipelines {
+        self.init[format.index()].get_or_init(|| InitPipelines::new(&self.device, format))
+    }
+
+    pub(crate) fn init_batch(&self) -> &InitPipelines {
+        self.init_batch.get_or_init(|| InitPipelines::batch(&self.device))
+    }
+
+    pub(crate) fn relabel(&self) -> &RelabelPipelines {
+        self.relabel.get_or_init(|| RelabelPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub(crate) fn stats(&self) -> &StatsPipelines {
+        self.stats.get_or_init(|| StatsPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub(crate) fn moments(&self) -> &MomentsPipelines {
+        self.moments.get_or_init(|| MomentsPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub(crate) fn classes(&self) -> &ClassesPipelines {
+        self.classes.get_or_init(|| ClassesPipelines::new(&self.device))
+    }
+
+    /// The classes pass reading the input of `format`.
+    pub(crate) fn store_classes(&self, format: InputFormat) -> &wgpu::ComputePipeline {
+        self.classes().store_pipeline(&self.device, self.init(format), format)
+    }
+
+    pub(crate) fn colorize(&self) -> &ColorizePipelines {
+        self.colorize.get_or_init(|| ColorizePipelines::new(&self.device))
+    }
+
+    pub(crate) fn filter(&self) -> &FilterPipelines {
+        self.filter.get_or_init(|| FilterPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub(crate) fn holes(&self) -> &HolesPipelines {
+        self.holes.get_or_init(|| HolesPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub(crate) fn boundary(&self) -> &BoundaryPipelines {
+        self.boundary.get_or_init(|| BoundaryPipelines::new(&self.device, &self.merge_bind_group_layout))
+    }
+
+    pub fn new(device: &wgpu::Device) -> CCLPipelines {
+        let compress_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("compress_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            // 16 bytes is a safe minimum for two u32s + padding
+                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let merge_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("merge_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None, // or Some(NonZeroU64::new(labels_size).unwrap())
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            // 16 bytes is a safe minimum for two u32s + padding
+                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let label_to_rgba_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("label_to_rgba_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba8Uint,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            // 16 bytes is a safe minimum for two u32s + padding
+                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let count_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("count_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(std::num::NonZeroU64::new(4).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader_string = include_wesl!("compress");
+        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
+
+        let compress_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compress Shader"),
+            source: shader_source,
+        });
+
+        let shader_string = include_wesl!("merge");
+        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
+
+        let merge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Merge Shader"),
+            source: shader_source,
+        });
+
+        let shader_string = include_wesl!("final_labeling");
+        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
+
+        let final_labeling_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Final Labeling Shader"),
+            source: shader_source,
+        });
+
+        let shader_string = include_wesl!("label_to_rgba");
+        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
+
+        let label_to_rgba_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Label to RGBA Shader"),
+            source: shader_source,
+        });
+
+        let shader_string = include_wesl!("count");
+        let shader_source = wgpu::ShaderSource::Wgsl(shader_string.into());
+
+        let count_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Count Shader"),
+            source: shader_source,
+        });
+
+        let compress_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("compress pipeline layout"),
+                bind_group_layouts: &[ &compress_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let merge_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("merge pipeline layout"),
+                bind_group_layouts: &[ &merge_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let label_to_rgba_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("label to rgba pipeline layout"),
+                bind_group_layouts: &[ &label_to_rgba_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let count_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("count pipeline layout"),
+                bind_group_layouts: &[ &merge_bind_group_layout, &count_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let compress_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compress Pipeline"),
+            layout: Some(&compress_pipeline_layout),
+            module: &compress_shader,
+            entry_point: "compress".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let merge_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("merge Pipeline"),
+            layout: Some(&merge_pipeline_layout),
+            module: &merge_shader,
+            entry_point: "merge".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let final_labeling_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("final labeling Pipeline"),
+            layout: Some(&merge_pipeline_layout),
+            module: &final_labeling_shader,
+            entry_point: "final_labeling".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let label_to_rgba_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Label to RGBA Pipeline"),
+            layout: Some(&label_to_rgba_pipeline_layout),
+            module: &label_to_rgba_shader,
+            entry_point: "label_to_rgba".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let count_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Count Pipeline"),
+            layout: Some(&count_pipeline_layout),
+            module: &count_shader,
+            entry_point: "count_roots".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            device: device.clone(),
+            init: [const { OnceLock::new() }; InputFormat::ALL.len()],
+            init_batch: OnceLock::new(),
+            compress_bind_group_layout,
+            merge_bind_group_layout,
+            label_to_rgba_bind_group_layout,
+            count_bind_group_layout,
+            compress_pipeline,
+            merge_pipeline,
+            final_labeling_pipeline,
+            label_to_rgba_pipeline,
+            count_pipeline,
+            relabel: OnceLock::new(),
+            stats: OnceLock::new(),
+            moments: OnceLock::new(),
+            classes: OnceLock::new(),
+            colorize: OnceLock::new(),
+            filter: OnceLock::new(),
+            holes: OnceLock::new(),
+            boundary: OnceLock::new(),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Reading buffers and textures back to the CPU.
+//!
+//! The reads never block the thread: the device is polled without waiting and the future
+//! yields back to the executor until the copy has finished and the staging buffer is
+//! mapped. Executors like `pollster` that poll again right away turn this into a busy
+//! wait, which is what the blocking methods of [`crate::Labeler`] do.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use flume::{TryRecvError, bounded};
+
+/// Maps `buffer` for reading, polling the device without blocking until it is mapped.
+async fn map_read(device: &wgpu::Device, buffer: &wgpu::Buffer) -> anyhow::Result<()> {
+    // The mapping process is async, so we'll need a channel to get the success flag
+    let (tx, rx) = bounded(1);
+    buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+        tx.send(result).unwrap()
+    });
+
+    // The callback will only get called after the device is polled
+    loop {
+        device.poll(wgpu::PollType::Poll)?;
+        match rx.try_recv() {
+            Ok(result) => return Ok(result?),
+            Err(TryRecvError::Empty) => YieldNow(false).await,
+            Err(TryRecvError::Disconnected) => anyhow::bail!("the buffer mapping callback was dropped"),
+        }
+    }
+}
+
+/// Returns `Pending` once, so other tasks get to run between two polls of the device.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Copies the first `len` elements of `buffer` into a staging buffer and reads them back.
+///
+/// `buffer` needs `COPY_SRC` usage and `len * size_of::<T>()` has to be a multiple of 4.
+pub async fn read_buffer<T: bytemuck::Pod>(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, len: u64) -> anyhow::Result<Vec<T>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let size = len * std::mem::size_of::<T>() as u64;
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    map_read(device, &staging_buffer).await?;
+
+    let data = bytemuck::cast_slice::<_, T>(&staging_buffer.get_mapped_range(..)).to_vec();
+    staging_buffer.unmap();
+    Ok(data)
+}
+
+/// Reads the first mip level of a 2D `texture` back, row by row without padding.
+///
+/// `texture` needs `COPY_SRC` usage and a format with `bytes_per_texel` bytes per texel.
+pub async fn read_texture(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture, bytes_per_texel: u32) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = (texture.width(), texture.height());
+    let row_bytes = width * bytes_per_texel;
+    // copies need rows aligned to 256 bytes
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging Buffer"),
+        size: padded_row_bytes as u64 * height as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &staging_buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    map_read(device, &staging_buffer).await?;
+
+    let data = staging_buffer
+        .get_mapped_range(..)
+        .chunks(padded_row_bytes as usize)
+        .flat_map(|row| &row[..row_bytes as usize])
+        .copied()
+        .collect();
+    staging_buffer.unmap();
+    Ok(data)
+}
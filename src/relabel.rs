@@ -0,0 +1,158 @@
+use wesl::include_wesl;
+
+use crate::scan::{PrefixSum, ScanPipelines};
+
+/// Pipelines of the optional relabel stage, which numbers the components 1..N.
+pub(crate) struct RelabelPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    init_firsts_pipeline: wgpu::ComputePipeline,
+    find_firsts_pipeline: wgpu::ComputePipeline,
+    flag_firsts_pipeline: wgpu::ComputePipeline,
+    relabel_pipeline: wgpu::ComputePipeline,
+    scan: ScanPipelines,
+}
+
+impl RelabelPipelines {
+    /// `merge_bind_group_layout` is bound to group 0, the relabel shaders read the labels,
+    /// infos and dimensions just like final_labeling.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> RelabelPipelines {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("relabel_bind_group_layout"),
+                entries: &[storage_entry(0), storage_entry(1)],
+            });
+
+        let shader_string = include_wesl!("relabel");
+        let relabel_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Relabel Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("relabel pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let init_firsts_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Init Firsts Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &relabel_shader,
+            entry_point: "init_firsts".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let find_firsts_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Find Firsts Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &relabel_shader,
+            entry_point: "find_firsts".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let flag_firsts_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Flag Firsts Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &relabel_shader,
+            entry_point: "flag_firsts".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let relabel_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Relabel Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &relabel_shader,
+            entry_point: "relabel".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            init_firsts_pipeline,
+            find_firsts_pipeline,
+            flag_firsts_pipeline,
+            relabel_pipeline,
+            scan: ScanPipelines::new(device),
+        }
+    }
+}
+
+/// The per-image buffers of the relabel stage.
+pub(crate) struct Relabel {
+    bind_group: wgpu::BindGroup,
+    prefix_sum: PrefixSum,
+}
+
+impl Relabel {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &RelabelPipelines, capacity: u64) -> Relabel {
+        let ids_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ids Buffer"),
+            size: capacity * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let firsts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Firsts Buffer"),
+            size: capacity * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("relabel_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: ids_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: firsts_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let prefix_sum = PrefixSum::new(device, &pipelines.scan, &ids_buffer, capacity);
+
+        Self {
+            bind_group,
+            prefix_sum,
+        }
+    }
+
+    pub(crate) fn set_len(&mut self, queue: &wgpu::Queue, num_pixels: u64) {
+        self.prefix_sum.set_len(queue, num_pixels);
+    }
+
+    /// Records the stage, which has to run after final_labeling. `merge_bind_group` and
+    /// `workgroups` are the ones final_labeling was dispatched with.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &RelabelPipelines, merge_bind_group: &wgpu::BindGroup, workgroups: (u32, u32)) {
+        for pipeline in [&pipelines.init_firsts_pipeline, &pipelines.find_firsts_pipeline, &pipelines.flag_firsts_pipeline] {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, merge_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+
+        self.prefix_sum.dispatch(compute_pass, &pipelines.scan);
+
+        compute_pass.set_pipeline(&pipelines.relabel_pipeline);
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+}
@@ -0,0 +1,189 @@
+use wesl::include_wesl;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+/// Elements scanned by one workgroup of `scan.wesl`.
+const PER_GROUP: u64 = 1024;
+/// Dispatches are limited to 65535 workgroups per dimension.
+const MAX_GROUPS_X: u64 = 65535;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScanParams {
+    len: u32,
+    groups_x: u32,
+    num_groups: u32,
+    _pad0: u32,
+}
+
+pub(crate) struct ScanPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    scan_pipeline: wgpu::ComputePipeline,
+    add_pipeline: wgpu::ComputePipeline,
+}
+
+impl ScanPipelines {
+    pub(crate) fn new(device: &wgpu::Device) -> ScanPipelines {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("scan_bind_group_layout"),
+                entries: &[
+                    storage_entry(0),
+                    storage_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(std::num::NonZeroU64::new(16).unwrap()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader_string = include_wesl!("scan");
+        let scan_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Scan Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("scan pipeline layout"),
+                bind_group_layouts: &[ &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let scan_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Scan Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &scan_shader,
+            entry_point: "scan_groups".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let add_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Add Offsets Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &scan_shader,
+            entry_point: "add_offsets".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            scan_pipeline,
+            add_pipeline,
+        }
+    }
+}
+
+struct ScanLevel {
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    workgroups: (u32, u32),
+}
+
+/// An in-place exclusive prefix sum over a buffer of u32s.
+///
+/// Every level scans its input in groups of 1024 and writes the group totals into the
+/// input of the next level, until a single group is left.
+pub(crate) struct PrefixSum {
+    levels: Vec<ScanLevel>,
+}
+
+impl PrefixSum {
+    /// Creates the levels for up to `capacity` elements of `data`. Call
+    /// [`PrefixSum::set_len`] before dispatching.
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &ScanPipelines, data: &wgpu::Buffer, capacity: u64) -> PrefixSum {
+        let mut levels = Vec::new();
+        let mut data = data.clone();
+        let mut len = capacity.max(1);
+        loop {
+            let num_groups = len.div_ceil(PER_GROUP);
+            let sums_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Scan Sums Buffer"),
+                size: num_groups * 4,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Scan Params Uniform"),
+                contents: bytemuck::cast_slice(&[ScanParams { len: 0, groups_x: 0, num_groups: 0, _pad0: 0 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("scan_bind_group"),
+                layout: &pipelines.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: data.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: sums_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            levels.push(ScanLevel { params_buffer, bind_group, workgroups: (0, 0) });
+
+            if num_groups == 1 {
+                return Self { levels };
+            }
+            data = sums_buffer;
+            len = num_groups;
+        }
+    }
+
+    /// Sets the number of elements that are scanned, at most the capacity.
+    pub(crate) fn set_len(&mut self, queue: &wgpu::Queue, len: u64) {
+        let mut len = len.max(1);
+        for level in &mut self.levels {
+            let num_groups = len.div_ceil(PER_GROUP);
+            let groups_x = num_groups.min(MAX_GROUPS_X);
+            let groups_y = num_groups.div_ceil(groups_x);
+            let params = ScanParams {
+                len: len as u32,
+                groups_x: groups_x as u32,
+                num_groups: num_groups as u32,
+                _pad0: 0,
+            };
+            queue.write_buffer(&level.params_buffer, 0, bytemuck::cast_slice(&[params]));
+            level.workgroups = (groups_x as u32, groups_y as u32);
+            len = num_groups;
+        }
+    }
+
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &ScanPipelines) {
+        compute_pass.set_pipeline(&pipelines.scan_pipeline);
+        for level in &self.levels {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups(level.workgroups.0, level.workgroups.1, 1);
+        }
+
+        // the last level consists of a single group, there is nothing to add to it
+        compute_pass.set_pipeline(&pipelines.add_pipeline);
+        for level in self.levels.iter().rev().skip(1) {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups(level.workgroups.0, level.workgroups.1, 1);
+        }
+    }
+}
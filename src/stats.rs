@@ -0,0 +1,226 @@
+use wesl::include_wesl;
+
+use crate::moments::{Moments, RawMoments};
+
+/// Area, bounding box, centroid and topology of one connected component.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComponentStats {
+    /// The consecutive label of the component, see [`crate::CCLOptions::relabel`].
+    pub label: u32,
+    /// Number of pixels.
+    pub area: u32,
+    pub min_x: u32,
+    pub min_y: u32,
+    /// Inclusive, like the minima.
+    pub max_x: u32,
+    pub max_y: u32,
+    /// Mean x and y of the pixels.
+    pub centroid: (f64, f64),
+    /// Whether the component has a pixel in the first or last row or column of the image,
+    /// i.e. it might be cut off by the image frame. See [`crate::CCLOptions::clear_border`].
+    pub touches_border: bool,
+    /// The number of components minus the number of holes of this component alone, so
+    /// 1 - `holes`. Holes are connected with the other connectivity than the component.
+    pub euler_number: i32,
+    /// The number of background regions the component encloses. Other components in them
+    /// do not fill them.
+    pub holes: u32,
+    /// Only computed if [`crate::CCLOptions::moments`] is enabled.
+    pub moments: Option<Moments>,
+}
+
+impl ComponentStats {
+    pub fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+/// One `ComponentStats` struct of `stats.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct RawStats {
+    area: u32,
+    inv_min_x: u32,
+    inv_min_y: u32,
+    max_x: u32,
+    max_y: u32,
+    sum_x_lo: u32,
+    sum_x_hi: u32,
+    sum_y_lo: u32,
+    sum_y_hi: u32,
+    touches_border: u32,
+    euler_quads: i32,
+}
+
+impl RawStats {
+    pub(crate) fn to_stats(self, label: u32, moments: Option<RawMoments>) -> ComponentStats {
+        let sum_x = (self.sum_x_hi as u64) << 32 | self.sum_x_lo as u64;
+        let sum_y = (self.sum_y_hi as u64) << 32 | self.sum_y_lo as u64;
+        let area = self.area.max(1) as f64;
+        ComponentStats {
+            label,
+            area: self.area,
+            min_x: !self.inv_min_x,
+            min_y: !self.inv_min_y,
+            max_x: self.max_x,
+            max_y: self.max_y,
+            centroid: (sum_x as f64 / area, sum_y as f64 / area),
+            touches_border: self.touches_border != 0,
+            euler_number: self.euler_quads / 4,
+            holes: (1 - self.euler_quads / 4).max(0) as u32,
+            moments: moments.map(|moments| moments.to_moments(self.area, sum_x, sum_y)),
+        }
+    }
+}
+
+pub(crate) struct StatsPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    accumulate_pipeline: wgpu::ComputePipeline,
+    mark_border_pipeline: wgpu::ComputePipeline,
+}
+
+impl StatsPipelines {
+    /// `merge_bind_group_layout` is bound to group 0, the stats of every block are
+    /// accumulated from its final labels.
+    pub(crate) fn new(device: &wgpu::Device, merge_bind_group_layout: &wgpu::BindGroupLayout) -> StatsPipelines {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("stats_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader_string = include_wesl!("stats");
+        let stats_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Stats Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("stats pipeline layout"),
+                bind_group_layouts: &[merge_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let accumulate_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Accumulate Stats Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &stats_shader,
+            entry_point: "accumulate_stats".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let mark_border_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Mark Border Stats Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &stats_shader,
+            entry_point: "mark_border".into(),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            accumulate_pipeline,
+            mark_border_pipeline,
+        }
+    }
+}
+
+/// The per-image stats buffer, one entry per possible component.
+pub(crate) struct Stats {
+    stats_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Whether the stats are accumulated, otherwise only the border flags are set for
+    /// [`crate::CCLOptions::clear_border`].
+    accumulate: bool,
+    /// Number of components the buffer can hold.
+    capacity: u64,
+    /// Number of entries that are cleared before every run.
+    len: u64,
+}
+
+impl Stats {
+    pub(crate) fn new(device: &wgpu::Device, pipelines: &StatsPipelines, capacity: u64) -> Stats {
+        let stats_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats Buffer"),
+            size: capacity * std::mem::size_of::<RawStats>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("stats_bind_group"),
+            layout: &pipelines.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: stats_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            stats_buffer,
+            bind_group,
+            accumulate: true,
+            capacity,
+            len: capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Sets the maximum number of components of the bound image.
+    pub(crate) fn set_len(&mut self, max_components: u64) {
+        self.len = max_components.min(self.capacity);
+    }
+
+    pub(crate) fn buffer(&self) -> &wgpu::Buffer {
+        &self.stats_buffer
+    }
+
+    pub(crate) fn accumulates(&self) -> bool {
+        self.accumulate
+    }
+
+    pub(crate) fn set_accumulate(&mut self, accumulate: bool) {
+        self.accumulate = accumulate;
+    }
+
+    /// Clears the entries of the bound image. Has to be recorded outside of a compute pass.
+    pub(crate) fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.len * std::mem::size_of::<RawStats>() as u64;
+        encoder.clear_buffer(&self.stats_buffer, 0, Some(size));
+    }
+
+    /// Records the accumulation, which has to run after the relabel stage. The border
+    /// flags are set by one thread per row and column of the image of `(width, height)`.
+    pub(crate) fn dispatch(&self, compute_pass: &mut wgpu::ComputePass, pipelines: &StatsPipelines, merge_bind_group: &wgpu::BindGroup, workgroups: (u32, u32), (width, height): (u32, u32)) {
+        compute_pass.set_bind_group(0, merge_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        if self.accumulate {
+            compute_pass.set_pipeline(&pipelines.accumulate_pipeline);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        compute_pass.set_pipeline(&pipelines.mark_border_pipeline);
+        compute_pass.dispatch_workgroups(width.max(height).div_ceil(64), 1, 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! The containment hierarchy of the foreground and background components, like the
+//! `RETR_TREE` hierarchy of OpenCV's contours.
+//!
+//! The regions alternate between foreground and background from the outside in: the
+//! background around everything, the objects, their holes, the islands in the holes and
+//! so on. This only nests properly if the background is labeled with the other
+//! connectivity than the foreground, see [`crate::Labeler::component_tree`].
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque, btree_map::Entry};
+
+use crate::LabelMap;
+
+/// A foreground or background component, by its label in the respective label map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Region {
+    /// A label of the foreground labels.
+    Foreground(u32),
+    /// A label of the background labels, see [`crate::CCLState::read_background_labels`].
+    Background(u32),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    parent: Option<Region>,
+    children: Vec<Region>,
+    depth: u32,
+}
+
+/// Which region lies inside which, built from the foreground and the background labels
+/// of the same image.
+#[derive(Clone, Debug)]
+pub struct ComponentTree {
+    nodes: BTreeMap<Region, Node>,
+}
+
+impl ComponentTree {
+    /// Builds the tree from the labels of [`crate::Phase::Both`]. The regions that touch
+    /// the border of the image are the roots, every other region is the child of the
+    /// neighbouring region that encloses it.
+    pub fn new(foreground: &LabelMap, background: &LabelMap) -> ComponentTree {
+        assert!(
+            foreground.width() == background.width() && foreground.height() == background.height(),
+            "the foreground and background labels have different dimensions"
+        );
+        let (width, height) = (foreground.width(), foreground.height());
+        let region_at = |x: u32, y: u32| match (foreground[(x, y)], background[(x, y)]) {
+            (0, 0) => None,
+            (0, label) => Some(Region::Background(label)),
+            (label, _) => Some(Region::Foreground(label)),
+        };
+
+        // a foreground and a background region that touch diagonally touch horizontally or
+        // vertically as well, so the right and lower neighbours find every pair. A pair
+        // touches along its whole boundary, the sets keep it once.
+        let mut neighbours: BTreeMap<Region, BTreeSet<Region>> = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        let mut nodes = BTreeMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                let Some(region) = region_at(x, y) else { continue };
+                if (x == 0 || y == 0 || x + 1 == width || y + 1 == height) && !nodes.contains_key(&region) {
+                    nodes.insert(region, Node::default());
+                    queue.push_back(region);
+                }
+                let right = (x + 1 < width).then(|| region_at(x + 1, y)).flatten();
+                let below = (y + 1 < height).then(|| region_at(x, y + 1)).flatten();
+                for other in [right, below].into_iter().flatten().filter(|&other| other != region) {
+                    neighbours.entry(region).or_default().insert(other);
+                    neighbours.entry(other).or_default().insert(region);
+                }
+            }
+        }
+
+        // breadth first from the border, so every region is reached from its enclosing one
+        while let Some(region) = queue.pop_front() {
+            let depth = nodes[&region].depth;
+            let mut children = Vec::new();
+            for &other in neighbours.get(&region).into_iter().flatten() {
+                if let Entry::Vacant(entry) = nodes.entry(other) {
+                    entry.insert(Node { parent: Some(region), children: Vec::new(), depth: depth + 1 });
+                    children.push(other);
+                    queue.push_back(other);
+                }
+            }
+            nodes.get_mut(&region).expect("queued regions have a node").children = children;
+        }
+
+        ComponentTree { nodes }
+    }
+
+    /// The regions that touch the border of the image, which have no parent.
+    pub fn roots(&self) -> impl Iterator<Item = Region> + '_ {
+        self.nodes.iter().filter(|(_, node)| node.parent.is_none()).map(|(&region, _)| region)
+    }
+
+    /// All regions, foreground first, in the order of their labels.
+    pub fn regions(&self) -> impl Iterator<Item = Region> + '_ {
+        self.nodes.keys().copied()
+    }
+
+    /// Whether the region is part of the tree.
+    pub fn contains(&self, region: Region) -> bool {
+        self.nodes.contains_key(&region)
+    }
+
+    /// The region that directly encloses `region`, `None` for the roots and regions that
+    /// are not in the tree.
+    pub fn parent(&self, region: Region) -> Option<Region> {
+        self.nodes.get(&region).and_then(|node| node.parent)
+    }
+
+    /// The regions directly enclosed by `region`, ordered like [`ComponentTree::regions`].
+    pub fn children(&self, region: Region) -> &[Region] {
+        self.nodes.get(&region).map_or(&[], |node| &node.children)
+    }
+
+    /// The number of regions around `region`, 0 for the roots. The holes of an object
+    /// lying in the background have depth 2, the islands in them depth 3.
+    pub fn depth(&self, region: Region) -> Option<u32> {
+        self.nodes.get(&region).map(|node| node.depth)
+    }
+
+    /// The holes of a foreground component, its background children.
+    pub fn holes(&self, label: u32) -> impl Iterator<Item = u32> + '_ {
+        self.children(Region::Foreground(label)).iter().filter_map(|&region| match region {
+            Region::Background(label) => Some(label),
+            Region::Foreground(_) => None,
+        })
+    }
+}
@@ -0,0 +1,537 @@
+//! Connected component labeling of voxel volumes, e.g. CT or microscopy stacks or a video
+//! with time as the third axis.
+//!
+//! The volume is split into 2x2x2 blocks, the 3D counterpart of the 2x2 blocks of
+//! [`crate::CCLState`], and the blocks are merged with the same union-find of
+//! `union_find.wesl` over labels indexed `(z * height + y) * width + x`. Every block is a
+//! node with 26-connectivity, with 6 and 18 the voxels of a block are not necessarily
+//! connected, so every voxel is a node of its own.
+
+use std::{ops::Index, sync::Arc};
+
+use wesl::include_wesl;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{Foreground, LabelMap, label_map, readback};
+
+/// Which voxels are neighbours.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VolumeConnectivity {
+    /// Only the voxels that share a face.
+    Six,
+    /// The voxels that share an edge as well.
+    Eighteen,
+    /// The voxels that share a corner as well.
+    #[default]
+    TwentySix,
+}
+
+impl VolumeConnectivity {
+    fn as_u32(self) -> u32 {
+        match self {
+            VolumeConnectivity::Six => 6,
+            VolumeConnectivity::Eighteen => 18,
+            VolumeConnectivity::TwentySix => 26,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VolumeOptions {
+    pub connectivity: VolumeConnectivity,
+    /// The test of the voxels that are labeled. Multi-value labeling is not supported for
+    /// volumes.
+    pub foreground: Foreground,
+}
+
+/// The `VolumeDimensions` uniform of `volume_dims.wesl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct VolumeDimensions {
+    columns: u32,
+    rows: u32,
+    slices: u32,
+    connectivity: u32,
+    voxel_nodes: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+/// The labels of a volume read back to the CPU.
+///
+/// Background voxels are `0`, every foreground voxel carries the index of the root of its
+/// component + 1. Labels are stored slice by slice, every slice row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumeLabels {
+    width: u32,
+    height: u32,
+    depth: u32,
+    labels: Vec<u32>,
+}
+
+impl VolumeLabels {
+    pub fn new(width: u32, height: u32, depth: u32, labels: Vec<u32>) -> VolumeLabels {
+        assert_eq!(
+            labels.len() as u64,
+            width as u64 * height as u64 * depth as u64,
+            "the number of labels does not match the dimensions"
+        );
+        Self { width, height, depth, labels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of slices, or frames of a video.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The label at `(x, y, z)`, or `None` if the position is outside the volume.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<u32> {
+        if x < self.width && y < self.height && z < self.depth {
+            Some(self.labels[self.index_of(x, y, z)])
+        } else {
+            None
+        }
+    }
+
+    /// The labels of slice `z`, which keep the labels of the whole volume. Panics if the
+    /// slice is outside the volume.
+    pub fn slice(&self, z: u32) -> LabelMap {
+        assert!(z < self.depth, "slice {z} is outside the volume");
+        let len = self.width as usize * self.height as usize;
+        let start = z as usize * len;
+        LabelMap::new(self.width, self.height, self.labels[start..start + len].to_vec())
+    }
+
+    pub fn as_slice(&self) -> &[u32] {
+        &self.labels
+    }
+
+    pub fn into_vec(self) -> Vec<u32> {
+        self.labels
+    }
+
+    /// Like [`LabelMap::same_components`].
+    pub fn same_components(&self, other: &VolumeLabels) -> bool {
+        (self.width, self.height, self.depth) == (other.width, other.height, other.depth) && label_map::same_components(&self.labels, &other.labels)
+    }
+
+    fn index_of(&self, x: u32, y: u32, z: u32) -> usize {
+        (z as usize * self.height as usize + y as usize) * self.width as usize + x as usize
+    }
+}
+
+impl Index<(u32, u32, u32)> for VolumeLabels {
+    type Output = u32;
+
+    /// The label at `(x, y, z)`. Panics if the position is outside the volume.
+    fn index(&self, (x, y, z): (u32, u32, u32)) -> &u32 {
+        assert!(x < self.width && y < self.height && z < self.depth, "({x}, {y}, {z}) is outside the volume");
+        &self.labels[self.index_of(x, y, z)]
+    }
+}
+
+/// Uploads equally sized images as the slices of an Rgba8Uint 3D texture, e.g. the
+/// slices of a scan or the frames of a video, to label with [`VolumeState`].
+pub fn upload_slices(device: &wgpu::Device, queue: &wgpu::Queue, slices: &[image::DynamicImage]) -> anyhow::Result<wgpu::Texture> {
+    let Some(first) = slices.first() else {
+        anyhow::bail!("a volume needs at least one slice");
+    };
+    let (width, height) = (first.width(), first.height());
+    anyhow::ensure!(
+        slices.iter().all(|slice| (slice.width(), slice.height()) == (width, height)),
+        "the slices have different dimensions"
+    );
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: slices.len() as u32,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Volume Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba8Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    for (z, slice) in slices.iter().enumerate() {
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: z as u32 },
+            },
+            &slice.to_rgba8(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d { depth_or_array_layers: 1, ..size },
+        );
+    }
+    Ok(texture)
+}
+
+/// The bind group layouts and compute pipelines of the volume passes. Like
+/// [`crate::CCLPipelines`] this is created once per device and shared.
+pub struct VolumePipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    count_bind_group_layout: wgpu::BindGroupLayout,
+    init_pipeline: wgpu::ComputePipeline,
+    merge_pipeline: wgpu::ComputePipeline,
+    compress_pipeline: wgpu::ComputePipeline,
+    final_pipeline: wgpu::ComputePipeline,
+    count_pipeline: wgpu::ComputePipeline,
+}
+
+impl VolumePipelines {
+    pub fn new(device: &wgpu::Device) -> VolumePipelines {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("volume_bind_group_layout"),
+                entries: &[
+                    buffer_entry(0, storage),
+                    buffer_entry(1, storage),
+                    buffer_entry(2, wgpu::BufferBindingType::Uniform),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Uint,
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    buffer_entry(4, wgpu::BufferBindingType::Uniform),
+                ],
+            });
+        let count_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("volume_count_bind_group_layout"),
+                entries: &[buffer_entry(0, storage)],
+            });
+
+        let shader_string = include_wesl!("volume");
+        let volume_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Volume Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+        let shader_string = include_wesl!("volume_merge");
+        let merge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Volume Merge Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("volume pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let count_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("volume count pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout, &count_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let create_pipeline = |label, layout, module, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                module,
+                entry_point: entry_point.into(),
+                compilation_options: Default::default(),
+                cache: Default::default(),
+            })
+        };
+        let init_pipeline = create_pipeline("Volume Init Pipeline", &pipeline_layout, &volume_shader, "init_volume");
+        let merge_pipeline = create_pipeline("Volume Merge Pipeline", &pipeline_layout, &merge_shader, "merge_volume");
+        let compress_pipeline = create_pipeline("Volume Compress Pipeline", &pipeline_layout, &merge_shader, "compress_volume");
+        let final_pipeline = create_pipeline("Volume Final Labeling Pipeline", &pipeline_layout, &volume_shader, "final_volume");
+        let count_pipeline = create_pipeline("Volume Count Pipeline", &count_pipeline_layout, &volume_shader, "count_volume");
+
+        Self {
+            bind_group_layout,
+            count_bind_group_layout,
+            init_pipeline,
+            merge_pipeline,
+            compress_pipeline,
+            final_pipeline,
+            count_pipeline,
+        }
+    }
+}
+
+/// The buffers and bind groups to label one volume, the 3D counterpart of
+/// [`crate::CCLState`].
+pub struct VolumeState {
+    pipelines: Arc<VolumePipelines>,
+    width: u32,
+    height: u32,
+    depth: u32,
+    /// Number of voxels the labels and info buffers can hold.
+    capacity: u64,
+    options: VolumeOptions,
+    // the number of channels of the texels
+    channels: u32,
+    dims_buffer: wgpu::Buffer,
+    foreground_buffer: wgpu::Buffer,
+    labels_buffer: wgpu::Buffer,
+    info_buffer: wgpu::Buffer,
+    count_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    count_bind_group: wgpu::BindGroup,
+}
+
+impl VolumeState {
+    /// Creates the pipelines and the state for one volume. Prefer
+    /// [`VolumeState::with_pipelines`] when more than one volume is labeled.
+    pub fn new(device: &wgpu::Device, volume: &wgpu::Texture) -> anyhow::Result<VolumeState> {
+        let pipelines = Arc::new(VolumePipelines::new(device));
+        Self::with_pipelines(device, pipelines, volume)
+    }
+
+    /// Creates the state for a 3D texture with unsigned integer texels and texture binding
+    /// usage, e.g. one of [`upload_slices`]. Single-channel texels read as grey like those
+    /// of an [`crate::Input`].
+    pub fn with_pipelines(device: &wgpu::Device, pipelines: Arc<VolumePipelines>, volume: &wgpu::Texture) -> anyhow::Result<VolumeState> {
+        check_volume(volume)?;
+        let size = volume.size();
+        let options = VolumeOptions::default();
+        let dims_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Volume Dimensions Uniform"),
+            contents: bytemuck::cast_slice(&[dims(size, options.connectivity)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let foreground_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Volume Foreground Uniform"),
+            contents: bytemuck::cast_slice(&[options.foreground.to_params(None).with_channels(volume.format().components() as u32)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let capacity = size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64;
+        let (labels_buffer, info_buffer) = create_storage_buffers(device, capacity);
+        let count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Volume Component Count Buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = create_bind_group(device, &pipelines, volume, &labels_buffer, &info_buffer, &dims_buffer, &foreground_buffer);
+        let count_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("volume_count_bind_group"),
+            layout: &pipelines.count_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: count_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            pipelines,
+            width: size.width,
+            height: size.height,
+            depth: size.depth_or_array_layers,
+            capacity,
+            options,
+            channels: volume.format().components() as u32,
+            dims_buffer,
+            foreground_buffer,
+            labels_buffer,
+            info_buffer,
+            count_buffer,
+            bind_group,
+            count_bind_group,
+        })
+    }
+
+    /// Binds a new volume to this state. The labels and info buffers are only reallocated
+    /// if the new volume has more voxels than they can hold.
+    pub fn set_input(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, volume: &wgpu::Texture) -> anyhow::Result<()> {
+        check_volume(volume)?;
+        let size = volume.size();
+        (self.width, self.height, self.depth) = (size.width, size.height, size.depth_or_array_layers);
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[dims(size, self.options.connectivity)]));
+        self.channels = volume.format().components() as u32;
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[self.options.foreground.to_params(None).with_channels(self.channels)]));
+
+        let num_voxels = self.num_voxels();
+        if num_voxels > self.capacity {
+            self.capacity = num_voxels.next_power_of_two();
+            (self.labels_buffer, self.info_buffer) = create_storage_buffers(device, self.capacity);
+        }
+        self.bind_group = create_bind_group(device, &self.pipelines, volume, &self.labels_buffer, &self.info_buffer, &self.dims_buffer, &self.foreground_buffer);
+        Ok(())
+    }
+
+    pub fn options(&self) -> VolumeOptions {
+        self.options
+    }
+
+    /// Sets the connectivity and the foreground test for the following calls to
+    /// [`VolumeState::compute`].
+    pub fn set_options(&mut self, queue: &wgpu::Queue, options: VolumeOptions) {
+        self.options = options;
+        queue.write_buffer(&self.dims_buffer, 0, bytemuck::cast_slice(&[dims(self.size_3d(), options.connectivity)]));
+        queue.write_buffer(&self.foreground_buffer, 0, bytemuck::cast_slice(&[options.foreground.to_params(None).with_channels(self.channels)]));
+    }
+
+    /// Width, height and depth of the bound volume.
+    pub fn size(&self) -> (u32, u32, u32) {
+        (self.width, self.height, self.depth)
+    }
+
+    fn size_3d(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.depth,
+        }
+    }
+
+    fn num_voxels(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.depth as u64
+    }
+
+    /// Records all passes into `encoder`. Returns the labels buffer, one u32 per voxel
+    /// like [`VolumeLabels`], and the component count buffer.
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) -> (wgpu::Buffer, wgpu::Buffer) {
+        let pipelines = &self.pipelines;
+        // 4x4x4 threads with 2x2x2 voxels each
+        let workgroups = (self.width.div_ceil(8), self.height.div_ceil(8), self.depth.div_ceil(8));
+        encoder.clear_buffer(&self.count_buffer, 0, None);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Volume Compute Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            for pipeline in [&pipelines.init_pipeline, &pipelines.merge_pipeline, &pipelines.compress_pipeline, &pipelines.final_pipeline] {
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+            }
+
+            compute_pass.set_pipeline(&pipelines.count_pipeline);
+            compute_pass.set_bind_group(1, &self.count_bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+        }
+        (self.labels_buffer.clone(), self.count_buffer.clone())
+    }
+
+    /// Reads the labels of the bound volume back to the CPU. The work recorded by
+    /// [`VolumeState::compute`] has to be submitted before.
+    pub async fn read_labels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<VolumeLabels> {
+        let labels = readback::read_buffer::<u32>(device, queue, &self.labels_buffer, self.num_voxels()).await?;
+        Ok(VolumeLabels::new(self.width, self.height, self.depth, labels))
+    }
+
+    pub async fn read_component_count(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<u32> {
+        let count = readback::read_buffer::<u32>(device, queue, &self.count_buffer, 1).await?;
+        Ok(count[0])
+    }
+}
+
+fn check_volume(volume: &wgpu::Texture) -> anyhow::Result<()> {
+    anyhow::ensure!(volume.dimension() == wgpu::TextureDimension::D3, "a volume has to be a 3D texture");
+    anyhow::ensure!(
+        volume.format().sample_type(None, None) == Some(wgpu::TextureSampleType::Uint),
+        "the volume has to have unsigned integer texels, not {:?}",
+        volume.format()
+    );
+    anyhow::ensure!(volume.usage().contains(wgpu::TextureUsages::TEXTURE_BINDING), "the volume needs texture binding usage");
+    Ok(())
+}
+
+fn dims(size: wgpu::Extent3d, connectivity: VolumeConnectivity) -> VolumeDimensions {
+    VolumeDimensions {
+        columns: size.width,
+        rows: size.height,
+        slices: size.depth_or_array_layers,
+        connectivity: connectivity.as_u32(),
+        voxel_nodes: (connectivity != VolumeConnectivity::TwentySix) as u32,
+        _pad0: 0,
+        _pad1: 0,
+        _pad2: 0,
+    }
+}
+
+/// Creates the labels and info buffers for `capacity` voxels.
+fn create_storage_buffers(device: &wgpu::Device, capacity: u64) -> (wgpu::Buffer, wgpu::Buffer) {
+    // one u32 per voxel
+    let size = capacity.checked_mul(4).expect("The volume was too big to create a storage buffer");
+    let create_buffer = |label| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    };
+    (create_buffer("Volume Labels Buffer"), create_buffer("Volume Info Buffer"))
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    pipelines: &VolumePipelines,
+    volume: &wgpu::Texture,
+    labels_buffer: &wgpu::Buffer,
+    info_buffer: &wgpu::Buffer,
+    dims_buffer: &wgpu::Buffer,
+    foreground_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    let view = volume.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("volume_bind_group"),
+        layout: &pipelines.bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: labels_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: info_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: dims_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: foreground_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn batched_tiles_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let (tile_width, tile_height) = (img.width() / 4, img.height() / 3);
+    let tiles: Vec<_> = (0..12).map(|i| img.crop_imm(i % 4 * tile_width, i / 4 * tile_height, tile_width, tile_height)).collect();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let (labels, counts) = labeler.label_batch(&tiles)?;
+        for ((tile, labels), count) in tiles.iter().zip(&labels).zip(counts) {
+            let expected = reference::label_image(tile, options);
+            assert!(labels.same_components(&expected), "{connectivity:?}-connectivity labels of a batched tile differ from the CPU reference");
+            let components = expected.as_slice().iter().copied().max().unwrap_or(0);
+            assert_eq!(count, components, "{connectivity:?}-connectivity batched tile component count");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn layers_are_labeled_independently() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // every layer is one component that would touch the ones of its neighbours
+    let full = image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(5, 3, image::Luma([255])));
+    let tiles = vec![full; 3];
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        let (labels, counts) = labeler.label_batch(&tiles)?;
+        assert_eq!(counts, [1, 1, 1], "{connectivity:?}-connectivity");
+        for labels in &labels {
+            // counted from the first pixel of the layer
+            assert!(labels.as_slice().iter().all(|&label| label == 1), "{connectivity:?}-connectivity");
+        }
+    }
+    Ok(())
+}
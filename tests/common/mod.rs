@@ -0,0 +1,35 @@
+//! Helpers shared by the integration tests.
+
+// every test binary compiles this module but only uses a part of it
+#![allow(dead_code)]
+
+use bke_ccl::Labeler;
+
+pub mod reference;
+
+/// A labeler on the default adapter. The GPU tests are `#[ignore]`d and run with
+/// `cargo test -- --ignored` on a machine with an adapter, where a missing one is an
+/// error instead of a silently passing test.
+pub fn labeler() -> Labeler {
+    Labeler::new().expect("the GPU tests need an adapter")
+}
+
+/// The image the demo labels as well.
+pub fn test_image() -> image::DynamicImage {
+    image::load_from_memory(include_bytes!("../../src/test.png")).expect("the test image is a valid PNG")
+}
+
+/// A 64x48 image whose channels are set independently of each other in blobs of a few
+/// pixels, so every [`bke_ccl::Foreground`] test sees a different mask.
+pub fn rgba_image() -> image::DynamicImage {
+    image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 48, |x, y| {
+        image::Rgba(std::array::from_fn(|channel| {
+            // a hash of the 4x4 cell of the pixel, mixed with the exact position for the
+            // ragged blob borders
+            let cell = (x / 4 * 31 + y / 4 * 17 + channel as u32 * 101).wrapping_mul(2654435761);
+            let pixel = (x * 7 + y * 3 + channel as u32).wrapping_mul(40503);
+            let value = (cell >> 24) as u8;
+            if (cell >> 16) & 1 == 0 && pixel >> 13 & 3 != 0 { value } else { 0 }
+        }))
+    }))
+}
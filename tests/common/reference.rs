@@ -0,0 +1,294 @@
+//! A plain union-find labeling on the CPU to check the GPU labels against.
+//!
+//! Much slower than [`bke_ccl::CCLState`], but simple enough to trust.
+
+use std::collections::{HashMap, HashSet};
+
+use bke_ccl::{CCLOptions, ComponentStats, Connectivity, Foreground, LabelMap, Phase, VolumeConnectivity, VolumeLabels};
+
+/// The foreground of `img` as the GPU sees it with the same [`Foreground`] test.
+pub fn foreground_mask(img: &image::DynamicImage, foreground: Foreground) -> Vec<bool> {
+    img.to_rgba8().pixels().map(|pixel| foreground.is_foreground(pixel.0)).collect()
+}
+
+/// Labels the foreground pixels of `mask`, which is stored row by row. The labels are
+/// consecutive and numbered in raster order of the first pixel of every component.
+pub fn label_mask(width: u32, height: u32, mask: &[bool], connectivity: Connectivity) -> LabelMap {
+    let values: Vec<_> = mask.iter().map(|&foreground| foreground.then_some(0)).collect();
+    label_values(width, height, &values, connectivity)
+}
+
+/// Like [`label_mask`] in multi-value mode: `None` is background and neighbours are only
+/// connected if they have the same value.
+pub fn label_values(width: u32, height: u32, values: &[Option<u32>], connectivity: Connectivity) -> LabelMap {
+    assert_eq!(values.len() as u64, width as u64 * height as u64, "the values do not match the dimensions");
+    let (width, height) = (width as usize, height as usize);
+    let mut parents: Vec<usize> = (0..values.len()).collect();
+
+    // the neighbours that come before a pixel in raster order, as (dx, dy)
+    let neighbours: &[(isize, isize)] = match connectivity {
+        Connectivity::Four => &[(-1, 0), (0, -1)],
+        Connectivity::Eight => &[(-1, 0), (-1, -1), (0, -1), (1, -1)],
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            if values[idx].is_none() {
+                continue;
+            }
+            for &(dx, dy) in neighbours {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= width as isize {
+                    continue;
+                }
+                let neighbour = ny as usize * width + nx as usize;
+                if values[neighbour] == values[idx] {
+                    union(&mut parents, idx, neighbour);
+                }
+            }
+        }
+    }
+
+    let mut ids = vec![0u32; values.len()];
+    let mut next = 0;
+    let mut labels = vec![0u32; values.len()];
+    for idx in 0..values.len() {
+        if values[idx].is_none() {
+            continue;
+        }
+        let root = find(&mut parents, idx);
+        if ids[root] == 0 {
+            next += 1;
+            ids[root] = next;
+        }
+        labels[idx] = ids[root];
+    }
+    LabelMap::new(width as u32, height as u32, labels)
+}
+
+/// Labels `img` with the same foreground, connectivity and pixel values as the GPU with
+/// `options`. With [`Phase::Both`] these are the foreground labels, see
+/// [`label_background`].
+pub fn label_image(img: &image::DynamicImage, options: CCLOptions) -> LabelMap {
+    let foreground = match options.phase {
+        Phase::Background => options.foreground.inverted(),
+        Phase::Foreground | Phase::Both => options.foreground,
+    };
+    let values: Vec<_> = img
+        .to_rgba8()
+        .pixels()
+        .map(|pixel| {
+            let value = options.multi_value.map_or(0, |value| value.of(pixel.0));
+            foreground.is_foreground(pixel.0).then_some(value)
+        })
+        .collect();
+    label_values(img.width(), img.height(), &values, options.connectivity)
+}
+
+/// The background labels of [`Phase::Both`].
+pub fn label_background(img: &image::DynamicImage, options: CCLOptions) -> LabelMap {
+    label_image(img, CCLOptions { phase: Phase::Background, ..options })
+}
+
+/// Sets the labels of the components with fewer than `min_area` pixels to 0.
+pub fn remove_small_components(label_map: &LabelMap, min_area: u32) -> LabelMap {
+    let mut areas = HashMap::new();
+    for &label in label_map.as_slice() {
+        *areas.entry(label).or_insert(0u32) += 1;
+    }
+    let labels = label_map
+        .as_slice()
+        .iter()
+        .map(|&label| if areas[&label] < min_area { 0 } else { label })
+        .collect();
+    LabelMap::new(label_map.width(), label_map.height(), labels)
+}
+
+/// Keeps only the `count` largest components, ties go to the lower label.
+pub fn keep_largest(label_map: &LabelMap, count: usize) -> LabelMap {
+    let mut areas = HashMap::new();
+    for &label in label_map.as_slice().iter().filter(|&&label| label != 0) {
+        *areas.entry(label).or_insert(0u32) += 1;
+    }
+    let mut largest: Vec<_> = areas.into_iter().collect();
+    largest.sort_by_key(|&(label, area)| (std::cmp::Reverse(area), label));
+    largest.truncate(count);
+    let kept: HashMap<_, _> = largest.into_iter().collect();
+    let labels = label_map
+        .as_slice()
+        .iter()
+        .map(|&label| if kept.contains_key(&label) { label } else { 0 })
+        .collect();
+    LabelMap::new(label_map.width(), label_map.height(), labels)
+}
+
+/// Sets the labels of the components that touch the border of the image to 0.
+pub fn clear_border(label_map: &LabelMap) -> LabelMap {
+    let (width, height) = (label_map.width(), label_map.height());
+    let border: HashSet<_> = label_map
+        .iter()
+        .filter(|&(x, y, _)| x == 0 || y == 0 || x + 1 == width || y + 1 == height)
+        .map(|(_, _, label)| label)
+        .collect();
+    let labels = label_map
+        .as_slice()
+        .iter()
+        .map(|&label| if border.contains(&label) { 0 } else { label })
+        .collect();
+    LabelMap::new(width, height, labels)
+}
+
+/// Fills the background components of `mask` that do not touch the border of the image,
+/// connected with `connectivity`.
+pub fn fill_holes(width: u32, height: u32, mask: &[bool], connectivity: Connectivity) -> Vec<bool> {
+    let background: Vec<_> = mask.iter().map(|&set| !set).collect();
+    let labels = label_mask(width, height, &background, connectivity);
+    let border: HashSet<_> = labels
+        .iter()
+        .filter(|&(x, y, _)| x == 0 || y == 0 || x + 1 == width || y + 1 == height)
+        .map(|(_, _, label)| label)
+        .collect();
+    labels.as_slice().iter().map(|&label| label == 0 || !border.contains(&label)).collect()
+}
+
+/// The number of holes of every component of `label_map`, keyed by label. A hole is a
+/// region of the other pixels enclosed by the component alone, connected with the other
+/// connectivity than `connectivity`.
+pub fn holes(label_map: &LabelMap, connectivity: Connectivity) -> HashMap<u32, u32> {
+    let mut bounds = HashMap::new();
+    for (x, y, label) in label_map.iter().filter(|&(_, _, label)| label != 0) {
+        let (min_x, min_y, max_x, max_y) = bounds.entry(label).or_insert((x, y, x, y));
+        *min_x = (*min_x).min(x);
+        *min_y = (*min_y).min(y);
+        *max_x = (*max_x).max(x);
+        *max_y = (*max_y).max(y);
+    }
+    bounds
+        .into_iter()
+        .map(|(label, (min_x, min_y, max_x, max_y))| {
+            // the bounding box with a frame of one pixel, so the outside is one region
+            let (width, height) = (max_x - min_x + 3, max_y - min_y + 3);
+            let outside: Vec<_> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let inside = x > 0 && y > 0 && x + 1 < width && y + 1 < height;
+                    !inside || label_map[(min_x + x - 1, min_y + y - 1)] != label
+                })
+                .collect();
+            let regions = label_mask(width, height, &outside, connectivity.complement());
+            let count = regions.as_slice().iter().copied().max().unwrap_or(0);
+            // the frame is the first region in raster order
+            (label, count.saturating_sub(1))
+        })
+        .collect()
+}
+
+/// The statistics of every component of `label_map` in the order of their labels,
+/// without the moments.
+pub fn stats(label_map: &LabelMap, connectivity: Connectivity) -> Vec<ComponentStats> {
+    let (width, height) = (label_map.width(), label_map.height());
+    let count = label_map.as_slice().iter().copied().max().unwrap_or(0);
+    let mut stats: Vec<_> = (1..=count)
+        .map(|label| ComponentStats {
+            label,
+            area: 0,
+            min_x: u32::MAX,
+            min_y: u32::MAX,
+            max_x: 0,
+            max_y: 0,
+            centroid: (0.0, 0.0),
+            touches_border: false,
+            euler_number: 0,
+            holes: 0,
+            moments: None,
+        })
+        .collect();
+    for (x, y, label) in label_map.iter().filter(|&(_, _, label)| label != 0) {
+        let stats = &mut stats[label as usize - 1];
+        stats.area += 1;
+        stats.min_x = stats.min_x.min(x);
+        stats.min_y = stats.min_y.min(y);
+        stats.max_x = stats.max_x.max(x);
+        stats.max_y = stats.max_y.max(y);
+        stats.centroid.0 += x as f64;
+        stats.centroid.1 += y as f64;
+        stats.touches_border |= x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+    }
+    let holes = holes(label_map, connectivity);
+    for stats in &mut stats {
+        stats.centroid = (stats.centroid.0 / stats.area as f64, stats.centroid.1 / stats.area as f64);
+        stats.holes = holes[&stats.label];
+        stats.euler_number = 1 - stats.holes as i32;
+    }
+    stats
+}
+
+/// Labels the foreground voxels of `mask`, which is stored slice by slice, like
+/// [`label_mask`] does for images.
+pub fn label_volume(width: u32, height: u32, depth: u32, mask: &[bool], connectivity: VolumeConnectivity) -> VolumeLabels {
+    let (width, height, depth) = (width as usize, height as usize, depth as usize);
+    assert_eq!(mask.len(), width * height * depth, "the mask does not match the dimensions");
+    let mut parents: Vec<usize> = (0..mask.len()).collect();
+    let max_steps = match connectivity {
+        VolumeConnectivity::Six => 1,
+        VolumeConnectivity::Eighteen => 2,
+        VolumeConnectivity::TwentySix => 3,
+    };
+    // the neighbours that come before a voxel in raster order, as (dx, dy, dz)
+    let mut neighbours = Vec::new();
+    for dz in -1..=0isize {
+        for dy in -1..=1isize {
+            for dx in -1..=1isize {
+                let before = dz < 0 || dy < 0 || (dy == 0 && dx < 0);
+                if before && dx.abs() + dy.abs() + dz.abs() <= max_steps {
+                    neighbours.push((dx, dy, dz));
+                }
+            }
+        }
+    }
+    let index = |x: usize, y: usize, z: usize| (z * height + y) * width + x;
+
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                if !mask[index(x, y, z)] {
+                    continue;
+                }
+                for &(dx, dy, dz) in &neighbours {
+                    let (nx, ny, nz) = (x as isize + dx, y as isize + dy, z as isize + dz);
+                    if nx < 0 || ny < 0 || nz < 0 || nx >= width as isize || ny >= height as isize {
+                        continue;
+                    }
+                    let neighbour = index(nx as usize, ny as usize, nz as usize);
+                    if mask[neighbour] {
+                        union(&mut parents, index(x, y, z), neighbour);
+                    }
+                }
+            }
+        }
+    }
+
+    let labels = (0..mask.len()).map(|idx| if mask[idx] { find(&mut parents, idx) as u32 + 1 } else { 0 }).collect();
+    VolumeLabels::new(width as u32, height as u32, depth as u32, labels)
+}
+
+fn find(parents: &mut [usize], n: usize) -> usize {
+    let mut root = n;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // path compression
+    let mut idx = n;
+    while parents[idx] != root {
+        let parent = parents[idx];
+        parents[idx] = root;
+        idx = parent;
+    }
+    root
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    parents[a.max(b)] = a.min(b);
+}
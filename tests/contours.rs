@@ -0,0 +1,56 @@
+mod common;
+
+use bke_ccl::*;
+
+fn label_map_from_fn(width: u32, height: u32, foreground: impl Fn(i64, i64) -> bool) -> LabelMap {
+    let labels = (0..width * height).map(|idx| u32::from(foreground((idx % width) as i64, (idx / width) as i64))).collect();
+    LabelMap::new(width, height, labels)
+}
+
+#[test]
+fn square_contour_is_clockwise() {
+    // a 2x2 square in the middle of a 4x4 image
+    let labels = (0..16).map(|idx| u32::from((1..3).contains(&(idx % 4)) && (1..3).contains(&(idx / 4)))).collect();
+    let label_map = LabelMap::new(4, 4, labels);
+    let contours = contours::trace_contours(&label_map, &contours::boundary_mask(&label_map));
+    assert_eq!(contours.len(), 1);
+    assert_eq!(contours[&1].points, [(1, 1), (2, 1), (2, 2), (1, 2)]);
+    assert_eq!(contours[&1].perimeter, 4.0);
+}
+
+#[test]
+fn diamond_contour_takes_diagonal_steps() {
+    let label_map = label_map_from_fn(5, 5, |x, y| (x - 2).abs() + (y - 2).abs() <= 2);
+    let contours = contours::trace_contours(&label_map, &contours::boundary_mask(&label_map));
+    assert_eq!(contours[&1].points, [(2, 0), (3, 1), (4, 2), (3, 3), (2, 4), (1, 3), (0, 2), (1, 1)]);
+    // eight diagonal steps
+    assert!((contours[&1].perimeter - 8.0 * std::f64::consts::SQRT_2).abs() < 1e-9, "perimeter {}", contours[&1].perimeter);
+}
+
+#[test]
+fn staircase_contour_mixes_straight_and_diagonal_steps() {
+    // a staircase of two pixels wide steps down to the right:
+    // X...
+    // XX..
+    // .XX.
+    // ..XX
+    let label_map = label_map_from_fn(4, 4, |x, y| x == y || x + 1 == y);
+    let contours = contours::trace_contours(&label_map, &contours::boundary_mask(&label_map));
+    assert_eq!(contours[&1].points, [(0, 0), (1, 1), (2, 2), (3, 3), (2, 3), (1, 2), (0, 1)]);
+    // five diagonal steps and the two straight ones at the ends
+    assert!((contours[&1].perimeter - (2.0 + 5.0 * std::f64::consts::SQRT_2)).abs() < 1e-9, "perimeter {}", contours[&1].perimeter);
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn contours_follow_the_gpu_boundary() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        let (label_map, contours) = labeler.label_with_contours(&img)?;
+        assert!(contours == contours::trace_contours(&label_map, &contours::boundary_mask(&label_map)), "{connectivity:?}-connectivity contours along the GPU boundary differ from the CPU boundary");
+        assert_eq!(contours.len(), labeler.count_components(&img)? as usize, "not every component has a contour");
+    }
+    Ok(())
+}
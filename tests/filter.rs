@@ -0,0 +1,116 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn small_components_are_removed() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let (filtered, mask) = labeler.remove_small_components(&img, 20)?;
+        let expected = reference::remove_small_components(&reference::label_image(&img, options), 20);
+        assert!(filtered.same_components(&expected), "{connectivity:?}-connectivity labels without small components differ from the CPU reference");
+        assert!(filtered.as_slice().iter().zip(&mask).all(|(&label, &set)| (label != 0) == set), "the mask differs from the remaining components");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn largest_components_are_kept() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        // ties go to the lower label, so the selection is checked on the same labels
+        let (relabeled, _) = labeler.label_with_stats(&img)?;
+        for count in [1, 3] {
+            let (largest, _) = labeler.keep_largest(&img, count)?;
+            assert!(largest.as_slice() == reference::keep_largest(&relabeled, count as usize).as_slice(), "{connectivity:?}-connectivity labels of the {count} largest components differ from the CPU reference");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn border_components_are_cleared() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let (cleared, _) = labeler.clear_border(&img)?;
+        let expected = reference::clear_border(&reference::label_image(&img, options));
+        assert!(cleared.same_components(&expected), "{connectivity:?}-connectivity labels without the border components differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn input_is_cleared_after_the_background_phase() -> anyhow::Result<()> {
+    let labeler = common::labeler();
+    let (device, queue) = (labeler.device(), labeler.queue());
+    let img = common::test_image();
+    let texture = texture::TextureUInt::from_image(device, queue, &img, None)?;
+    let mut state = CCLState::new(device, queue, &texture)?;
+    let options = CCLOptions { min_area: Some(20), clear_input: true, phase: Phase::Both, ..Default::default() };
+    state.set_options(device, queue, options);
+    let run = |state: &CCLState| -> anyhow::Result<(LabelMap, LabelMap)> {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        state.compute(&mut encoder)?;
+        queue.submit([encoder.finish()]);
+        let labels = pollster::block_on(state.read_labels(device, queue))?;
+        let background = pollster::block_on(state.read_background_labels(device, queue))?;
+        Ok((labels, background))
+    };
+
+    let (filtered, background) = run(&state)?;
+    let expected = reference::remove_small_components(&reference::label_image(&img, options), 20);
+    assert!(filtered.same_components(&expected), "labels without small components differ from the CPU reference");
+    assert!(background.same_components(&reference::label_background(&img, options)), "the background was labeled on the cleared input");
+    // the next call labels the cleared input, in which only the kept components are left
+    let (relabeled, _) = run(&state)?;
+    assert!(relabeled.same_components(&filtered), "the removed components are still in the input");
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn largest_components_are_chosen_among_the_other_filters() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // the largest component touches the border, the two inner ones do not
+    let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(12, 8, |x, y| {
+        let border = x < 4;
+        let large = (6..9).contains(&x) && (1..4).contains(&y);
+        let small = (6..8).contains(&x) && (5..7).contains(&y);
+        image::Luma([if border || large || small { 255 } else { 0 }])
+    }));
+    let options = CCLOptions { clear_border: true, ..Default::default() };
+    labeler.set_options(options);
+    let (largest, mask) = labeler.keep_largest(&img, 1)?;
+    let expected = reference::keep_largest(&reference::clear_border(&reference::label_image(&img, options)), 1);
+    assert!(largest.same_components(&expected), "the largest inner component was not kept");
+    assert_eq!(mask.iter().filter(|&&set| set).count(), 9);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn clear_border_alone_does_not_accumulate_stats() -> anyhow::Result<()> {
+    let labeler = common::labeler();
+    let (device, queue) = (labeler.device(), labeler.queue());
+    let texture = texture::TextureUInt::from_image(device, queue, &common::test_image(), None)?;
+    let mut state = CCLState::new(device, queue, &texture)?;
+    state.set_options(device, queue, CCLOptions { clear_border: true, ..Default::default() });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    state.compute(&mut encoder)?;
+    queue.submit([encoder.finish()]);
+    assert!(pollster::block_on(state.read_stats(device, queue)).is_err());
+    Ok(())
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn holes_are_filled() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let mask = reference::foreground_mask(&img, Foreground::default());
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        for background_connectivity in [Connectivity::Eight, Connectivity::Four] {
+            let filled = labeler.fill_holes(&img, background_connectivity)?;
+            assert!(filled == reference::fill_holes(img.width(), img.height(), &mask, background_connectivity), "holes filled with {background_connectivity:?}-connectivity differ from the CPU reference");
+        }
+    }
+    Ok(())
+}
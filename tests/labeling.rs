@@ -0,0 +1,285 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn labels_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let label_map = labeler.label(&img)?;
+        assert!(label_map.same_components(&reference::label_image(&img, options)), "{connectivity:?}-connectivity labels differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn relabeled_components_are_numbered_by_their_first_pixel() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, relabel: true, ..Default::default() };
+        labeler.set_options(options);
+        let label_map = labeler.label(&img)?;
+
+        // every new label is one more than the largest before it in raster order
+        let mut count = 0;
+        for (x, y, label) in label_map.iter() {
+            assert!(label <= count + 1, "{connectivity:?}-connectivity label {label} at ({x}, {y}) comes before label {}", count + 1);
+            count = count.max(label);
+        }
+        assert_eq!(count, labeler.count_components(&img)?, "{connectivity:?}-connectivity labels are not 1..=N");
+        assert!(label_map.same_components(&reference::label_image(&img, options)), "{connectivity:?}-connectivity relabeled labels differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn blocks_only_connect_through_adjacent_pixels() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // the lower right pixels of the blocks of every other row, separated by a background
+    // row from each other. The blocks are neighbours, the pixels are not.
+    let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(4, 8, |x, y| {
+        image::Luma([if x % 2 == 1 && y % 2 == 1 { 255 } else { 0 }])
+    }));
+    let options = CCLOptions::default();
+    labeler.set_options(options);
+    let label_map = labeler.label(&img)?;
+    assert!(label_map.same_components(&reference::label_image(&img, options)), "8-connectivity labels differ from the CPU reference");
+    assert_eq!(labeler.count_components(&img)?, 8);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn multi_value_labels_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, multi_value: Some(PixelValue::Rgba), ..Default::default() };
+        labeler.set_options(options);
+        let label_map = labeler.label(&img)?;
+        assert!(label_map.same_components(&reference::label_image(&img, options)), "{connectivity:?}-connectivity multi-value labels differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn background_labels_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let (foreground, background) = labeler.label_both(&img)?;
+        assert!(foreground.same_components(&reference::label_image(&img, options)), "{connectivity:?}-connectivity foreground labels differ from the CPU reference");
+        assert!(background.same_components(&reference::label_background(&img, options)), "{connectivity:?}-connectivity background labels differ from the CPU reference");
+    }
+    Ok(())
+}
+
+/// The number of distinct components of a reference labeling.
+fn component_count(label_map: &LabelMap) -> u32 {
+    label_map.as_slice().iter().filter(|&&label| label != 0).collect::<std::collections::BTreeSet<_>>().len() as u32
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn component_counts_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        assert_eq!(labeler.count_components(&img)?, component_count(&reference::label_image(&img, options)), "{connectivity:?}-connectivity component count");
+
+        let (device, queue) = (labeler.device(), labeler.queue());
+        let texture = texture::TextureUInt::from_image(device, queue, &img, None)?;
+        let mut state = CCLState::new(device, queue, &texture)?;
+        state.set_options(device, queue, CCLOptions { phase: Phase::Both, ..options });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        state.compute(&mut encoder)?;
+        queue.submit([encoder.finish()]);
+        let background_count = pollster::block_on(state.read_background_count(device, queue))?;
+        assert_eq!(background_count, component_count(&reference::label_background(&img, options)), "{connectivity:?}-connectivity background count");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn bit_packed_input_matches_the_image() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let label_map = labeler.label(&img)?;
+    let mask = reference::foreground_mask(&img, Foreground::default());
+    let bits = Input::upload_bits(labeler.device(), labeler.queue(), img.width(), img.height(), &mask)?;
+    assert!(labeler.label_input(bits)?.same_components(&label_map), "the labels of the bit-packed mask differ");
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn images_wider_than_the_maximum_are_rejected() -> anyhow::Result<()> {
+    let labeler = common::labeler();
+    let (device, queue) = (labeler.device(), labeler.queue());
+    let wide = MAX_IMAGE_SIDE + 1;
+    let input = Input::upload_bytes(device, queue, wide, 1, &vec![255; wide as usize])?;
+    assert!(CCLState::new(device, queue, input).is_err());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn every_foreground_test_matches_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::rgba_image();
+    let tests = [
+        ForegroundTest::Channel(Channel::R),
+        ForegroundTest::Channel(Channel::G),
+        ForegroundTest::Channel(Channel::B),
+        ForegroundTest::Channel(Channel::A),
+        ForegroundTest::AnyChannel,
+        ForegroundTest::Luminance { threshold: 60 },
+        ForegroundTest::Alpha { threshold: 100 },
+    ];
+    for foreground in tests.into_iter().map(Foreground::new).flat_map(|foreground| [foreground, foreground.inverted()]) {
+        let options = CCLOptions { foreground, ..Default::default() };
+        labeler.set_options(options);
+        let label_map = labeler.label(&img)?;
+        let mask = reference::foreground_mask(&img, foreground);
+        assert!(mask.contains(&true) && mask.contains(&false), "{foreground:?} does not split the test image");
+        let expected = reference::label_mask(img.width(), img.height(), &mask, options.connectivity);
+        assert!(label_map.same_components(&expected), "labels with {foreground:?} differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn classes_are_the_values_of_the_components() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::rgba_image();
+    let pixels = img.to_rgba8();
+    for value in [PixelValue::Channel(Channel::G), PixelValue::Rgba] {
+        for connectivity in [Connectivity::Eight, Connectivity::Four] {
+            let options = CCLOptions { connectivity, foreground: Foreground::new(ForegroundTest::AnyChannel), multi_value: Some(value), ..Default::default() };
+            labeler.set_options(options);
+            let (label_map, classes) = labeler.label_with_classes(&img)?;
+            assert!(label_map.same_components(&reference::label_image(&img, options)), "{connectivity:?}-connectivity labels with {value:?} differ from the CPU reference");
+            assert_eq!(classes.len() as u32, label_map.as_slice().iter().copied().max().unwrap_or(0), "one class for every component");
+            for (x, y, label) in label_map.iter().filter(|&(_, _, label)| label != 0) {
+                assert_eq!(classes[label as usize - 1], value.of(pixels.get_pixel(x, y).0), "{connectivity:?}-connectivity class of label {label} at ({x}, {y}) with {value:?}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A render target with the `texels` of the first mip level, row by row.
+fn render_target(labeler: &Labeler, format: wgpu::TextureFormat, (width, height): (u32, u32), mip_level_count: u32, texels: &[u8]) -> wgpu::Texture {
+    let texture = labeler.device().create_texture(&wgpu::TextureDescriptor {
+        label: Some("Render Target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    labeler.queue().write_texture(
+        texture.as_image_copy(),
+        texels,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(texels.len() as u32 / height),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    texture
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn render_targets_match_the_image() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let size = (img.width(), img.height());
+    let label_map = labeler.label(&img)?;
+    let pixels = img.to_rgba8();
+    let red: Vec<f32> = pixels.pixels().map(|pixel| pixel.0[0] as f32 / 255.0).collect();
+
+    let targets = [
+        render_target(&labeler, wgpu::TextureFormat::Rgba8Unorm, size, 1, pixels.as_raw()),
+        render_target(&labeler, wgpu::TextureFormat::R32Float, size, 1, bytemuck::cast_slice(&red)),
+    ];
+    for target in &targets {
+        let input = Input::texture_view(target, &Default::default())?;
+        assert_eq!(input.size(), size);
+        assert!(labeler.label_input(input)?.same_components(&label_map), "the labels of the {:?} render target differ", target.format());
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn texture_views_take_the_size_of_their_mip_level() -> anyhow::Result<()> {
+    let labeler = common::labeler();
+    let texture = render_target(&labeler, wgpu::TextureFormat::Rgba8Unorm, (16, 10), 2, &[255; 16 * 10 * 4]);
+    let mip = Input::texture_view(&texture, &wgpu::TextureViewDescriptor { base_mip_level: 1, ..Default::default() })?;
+    assert_eq!(mip.size(), (8, 5));
+    let array = wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::D2Array), ..Default::default() };
+    assert!(Input::texture_view(&texture, &array).is_err(), "array views are not 2D");
+    let missing = wgpu::TextureViewDescriptor { base_mip_level: 2, ..Default::default() };
+    assert!(Input::texture_view(&texture, &missing).is_err(), "the texture has two mip levels");
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn single_channel_inputs_read_as_grey() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let (width, height) = (img.width(), img.height());
+    let mask = reference::foreground_mask(&img, Foreground::default());
+    let bytes: Vec<u8> = mask.iter().map(|&set| if set { 255 } else { 0 }).collect();
+    let words: Vec<u32> = bytes.iter().map(|&byte| byte as u32).collect();
+    let grey = image::DynamicImage::ImageLuma8(image::GrayImage::from_raw(width, height, bytes.clone()).expect("one byte per pixel"));
+    let r32_uint = render_target(&labeler, wgpu::TextureFormat::R32Uint, (width, height), 1, bytemuck::cast_slice(&words));
+    let r8_unorm = render_target(&labeler, wgpu::TextureFormat::R8Unorm, (width, height), 1, &bytes);
+    let expected = reference::label_mask(width, height, &mask, Connectivity::Eight);
+
+    // every test a grey pixel passes with any value above 0
+    let tests = [
+        ForegroundTest::Channel(Channel::R),
+        ForegroundTest::Channel(Channel::G),
+        ForegroundTest::Channel(Channel::B),
+        ForegroundTest::AnyChannel,
+        ForegroundTest::Luminance { threshold: 0 },
+    ];
+    for test in tests {
+        labeler.set_options(CCLOptions { foreground: Foreground::new(test), ..Default::default() });
+        let inputs = [
+            Input::upload_r8(labeler.device(), labeler.queue(), width, height, &bytes)?,
+            Input::texture_view(&r32_uint, &Default::default())?,
+            Input::texture_view(&r8_unorm, &Default::default())?,
+            Input::upload_bytes(labeler.device(), labeler.queue(), width, height, &bytes)?,
+            Input::upload_bits(labeler.device(), labeler.queue(), width, height, &mask)?,
+        ];
+        assert!(labeler.label(&grey)?.same_components(&expected), "the labels of the Rgba8 input with {test:?} differ from the mask");
+        for input in inputs {
+            let format = input.format();
+            assert!(labeler.label_input(input)?.same_components(&expected), "the labels of the {format:?} input with {test:?} differ from the mask");
+        }
+    }
+    Ok(())
+}
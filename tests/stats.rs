@@ -0,0 +1,105 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+fn image_from_fn(width: u32, height: u32, foreground: impl Fn(i32, i32) -> bool) -> image::DynamicImage {
+    image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(width, height, |x, y| {
+        image::Luma([if foreground(x as i32, y as i32) { 255 } else { 0 }])
+    }))
+}
+
+/// The Euler numbers and hole counts of the components of `img`, in the order of their labels.
+fn euler_and_holes(labeler: &mut Labeler, img: &image::DynamicImage, connectivity: Connectivity) -> anyhow::Result<Vec<(i32, u32)>> {
+    labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+    let (_, stats) = labeler.label_with_stats(img)?;
+    Ok(stats.iter().map(|stats| (stats.euler_number, stats.holes)).collect())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn ring_has_one_hole() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // without diagonal steps, so the hole is the same for both connectivities
+    let ring = image_from_fn(9, 9, |x, y| (2 * 2..=3 * 3).contains(&((x - 4).pow(2) + (y - 4).pow(2))));
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        assert_eq!(euler_and_holes(&mut labeler, &ring, connectivity)?, [(0, 1)], "{connectivity:?}-connectivity");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn figure_eight_has_two_holes() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let eight = image_from_fn(7, 12, |x, y| {
+        let frame = x == 1 || x == 5 || y == 1 || y == 10;
+        (1..=5).contains(&x) && (1..=10).contains(&y) && (frame || y == 5 || y == 6)
+    });
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        assert_eq!(euler_and_holes(&mut labeler, &eight, connectivity)?, [(-1, 2)], "{connectivity:?}-connectivity");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn diamond_is_a_ring_with_eight_connectivity_only() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // four pixels around the center that only touch diagonally
+    let diamond = image_from_fn(5, 5, |x, y| (x - 2).abs() + (y - 2).abs() == 1);
+    assert_eq!(euler_and_holes(&mut labeler, &diamond, Connectivity::Eight)?, [(0, 1)]);
+    assert_eq!(euler_and_holes(&mut labeler, &diamond, Connectivity::Four)?, [(1, 0); 4]);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn hole_counts_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        let (label_map, stats) = labeler.label_with_stats(&img)?;
+        let holes = reference::holes(&label_map, connectivity);
+        assert!(stats.iter().all(|stats| stats.holes == holes[&stats.label] && stats.euler_number == 1 - stats.holes as i32), "{connectivity:?}-connectivity hole counts differ from the CPU reference");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn border_flags_match_the_bounding_boxes() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let (_, stats) = labeler.label_with_stats(&img)?;
+    for stats in &stats {
+        let touches = stats.min_x == 0 || stats.min_y == 0 || stats.max_x + 1 == img.width() || stats.max_y + 1 == img.height();
+        assert_eq!(stats.touches_border, touches, "border flag of label {}", stats.label);
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn stats_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        let options = CCLOptions { connectivity, ..Default::default() };
+        labeler.set_options(options);
+        let (_, stats) = labeler.label_with_stats(&img)?;
+        let expected = reference::stats(&reference::label_image(&img, options), connectivity);
+        assert_eq!(stats.len(), expected.len(), "{connectivity:?}-connectivity component count");
+        for (stats, expected) in stats.iter().zip(&expected) {
+            let label = expected.label;
+            assert_eq!(stats.label, label);
+            assert_eq!(stats.area, expected.area, "{connectivity:?}-connectivity area of label {label}");
+            assert_eq!((stats.min_x, stats.min_y, stats.max_x, stats.max_y), (expected.min_x, expected.min_y, expected.max_x, expected.max_y), "{connectivity:?}-connectivity bounding box of label {label}");
+            assert!((stats.centroid.0 - expected.centroid.0).abs() < 1e-6 && (stats.centroid.1 - expected.centroid.1).abs() < 1e-6, "{connectivity:?}-connectivity centroid of label {label}: {:?} instead of {:?}", stats.centroid, expected.centroid);
+            assert_eq!(stats.touches_border, expected.touches_border, "{connectivity:?}-connectivity border flag of label {label}");
+            assert_eq!((stats.euler_number, stats.holes), (expected.euler_number, expected.holes), "{connectivity:?}-connectivity Euler number and holes of label {label}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use bke_ccl::*;
+use common::reference;
+
+/// A washer with an island in its hole, nested in the background around it.
+fn washer() -> image::DynamicImage {
+    image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(13, 13, |x, y| {
+        let (dx, dy) = (x as i32 - 6, y as i32 - 6);
+        image::Luma([if (9..=25).contains(&(dx * dx + dy * dy)) || (dx, dy) == (0, 0) { 255 } else { 0 }])
+    }))
+}
+
+fn assert_washer_nests(tree: &ComponentTree, connectivity: Connectivity) {
+    let nesting = [Region::Background(1), Region::Foreground(1), Region::Background(2), Region::Foreground(2)];
+    assert!(tree.regions().count() == nesting.len() && tree.roots().eq([nesting[0]]), "{connectivity:?}-connectivity washer should have four regions in one tree");
+    for (depth, pair) in nesting.windows(2).enumerate() {
+        assert_eq!(tree.parent(pair[1]), Some(pair[0]), "{connectivity:?}-connectivity");
+        assert_eq!(tree.children(pair[0]), [pair[1]], "{connectivity:?}-connectivity");
+        assert_eq!(tree.depth(pair[1]), Some(depth as u32 + 1), "{connectivity:?}-connectivity");
+    }
+    assert!(tree.holes(1).eq([2]));
+}
+
+#[test]
+fn washer_nests_on_the_cpu() {
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        // the background with the other connectivity, like Labeler::component_tree
+        let foreground = reference::label_image(&washer(), CCLOptions { connectivity, ..Default::default() });
+        let background = reference::label_background(&washer(), CCLOptions { connectivity: connectivity.complement(), ..Default::default() });
+        let tree = ComponentTree::new(&foreground, &background);
+        assert_washer_nests(&tree, connectivity);
+    }
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn washer_nests_on_the_gpu() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        let (_, _, tree) = labeler.component_tree(&washer())?;
+        assert_washer_nests(&tree, connectivity);
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn tree_holes_match_the_hole_counts() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for connectivity in [Connectivity::Eight, Connectivity::Four] {
+        labeler.set_options(CCLOptions { connectivity, ..Default::default() });
+        let (_, stats) = labeler.label_with_stats(&img)?;
+        let (_, _, tree) = labeler.component_tree(&img)?;
+        assert!(stats.iter().all(|stats| tree.holes(stats.label).count() == stats.holes as usize), "{connectivity:?}-connectivity holes in the component tree differ from the hole counts");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn filters_do_not_cut_regions_off_the_tree() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // would remove both foreground components of the washer
+    labeler.set_options(CCLOptions { min_area: Some(1000), clear_border: true, ..Default::default() });
+    let (_, _, tree) = labeler.component_tree(&washer())?;
+    assert_washer_nests(&tree, Connectivity::Eight);
+    Ok(())
+}
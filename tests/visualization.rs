@@ -0,0 +1,98 @@
+mod common;
+
+use bke_ccl::*;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn label_rgba_leaves_the_input_unchanged() -> anyhow::Result<()> {
+    let labeler = common::labeler();
+    let (device, queue) = (labeler.device(), labeler.queue());
+    let img = common::test_image();
+    let pixels = img.to_rgba8();
+    let (width, height) = pixels.dimensions();
+    let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Input Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        pixels.as_raw(),
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+
+    let mut state = CCLState::new(device, queue, Input::texture_view(&texture, &Default::default())?)?;
+    state.set_options(device, queue, CCLOptions { label_rgba: true, ..Default::default() });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let output = state.compute(&mut encoder)?;
+    queue.submit([encoder.finish()]);
+
+    let input = pollster::block_on(readback::read_texture(device, queue, &texture, 4))?;
+    assert!(input == pixels.as_raw().as_slice(), "computing the labels changed the input texture");
+    let label_rgba = output.label_rgba.expect("label_rgba is enabled");
+    let packed = pollster::block_on(readback::read_texture(device, queue, &label_rgba, 4))?;
+    let label_map = pollster::block_on(state.read_labels(device, queue))?;
+    assert!(packed == bytemuck::cast_slice::<u32, u8>(label_map.as_slice()), "the label_rgba texels differ from the bytes of the labels");
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn colors_only_depend_on_the_seed() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    for palette in [Palette::GoldenRatio, Palette::Hash] {
+        for transparent_background in [false, true] {
+            let colorize = ColorOptions { palette, seed: 7, transparent_background };
+            labeler.set_options(CCLOptions { colorize: Some(colorize), ..Default::default() });
+            let (label_map, first) = labeler.label_colorized(&img)?;
+            let (_, second) = labeler.label_colorized(&img)?;
+            assert!(first == second, "two {palette:?} runs with the same seed differ");
+
+            let background = if transparent_background { [0, 0, 0, 0] } else { [0, 0, 0, 255] };
+            for (&label, pixel) in label_map.as_slice().iter().zip(first.pixels()) {
+                assert_eq!(label == 0, pixel.0 == background, "{palette:?} colors {pixel:?} for label {label}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn overlay_mixes_the_colors_into_the_components() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    let source = img.to_rgba8();
+    let (_, colorized) = labeler.label_colorized(&img)?;
+
+    let (label_map, overlay) = labeler.label_overlay(&img, OverlayOptions::default())?;
+    let (_, again) = labeler.label_overlay(&img, OverlayOptions::default())?;
+    assert!(overlay == again, "two overlays with the same options differ");
+    for ((&label, pixel), source) in label_map.as_slice().iter().zip(overlay.pixels()).zip(source.pixels()) {
+        if label == 0 {
+            assert_eq!(pixel, source, "the overlay changed a background pixel");
+        }
+    }
+
+    let (_, hidden) = labeler.label_overlay(&img, OverlayOptions { opacity: 0.0, boundary_color: None })?;
+    assert!(hidden == source, "an overlay without opacity differs from the source");
+
+    let (_, opaque) = labeler.label_overlay(&img, OverlayOptions { opacity: 1.0, boundary_color: None })?;
+    for (((&label, pixel), color), source) in label_map.as_slice().iter().zip(opaque.pixels()).zip(colorized.pixels()).zip(source.pixels()) {
+        let expected = if label == 0 { source.0 } else { [color.0[0], color.0[1], color.0[2], source.0[3]] };
+        assert_eq!(pixel.0, expected, "an opaque overlay differs from the colors of label {label}");
+    }
+    Ok(())
+}
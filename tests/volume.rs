@@ -0,0 +1,45 @@
+mod common;
+
+use std::collections::HashSet;
+
+use bke_ccl::*;
+use common::reference;
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn volume_labels_match_the_reference() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    let img = common::test_image();
+    // the test image and shifted copies of it, like the frames of a video
+    let slices: Vec<_> = (0..4).map(|z| img.crop_imm(z * 3, z, img.width() - 9, img.height() - 3)).collect();
+    let (width, height, depth) = (slices[0].width(), slices[0].height(), slices.len() as u32);
+    let mask: Vec<_> = slices.iter().flat_map(|slice| reference::foreground_mask(slice, Foreground::default())).collect();
+    for connectivity in [VolumeConnectivity::TwentySix, VolumeConnectivity::Eighteen, VolumeConnectivity::Six] {
+        let (labels, count) = labeler.label_volume(&slices, VolumeOptions { connectivity, ..Default::default() })?;
+        let expected = reference::label_volume(width, height, depth, &mask, connectivity);
+        assert!(labels.same_components(&expected), "{connectivity:?}-connectivity volume labels differ from the CPU reference");
+        let components: HashSet<_> = expected.as_slice().iter().filter(|&&label| label != 0).collect();
+        assert_eq!(count as usize, components.len(), "{connectivity:?}-connectivity volume component count");
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a GPU, run with --ignored"]
+fn diagonal_voxels_depend_on_the_connectivity() -> anyhow::Result<()> {
+    let mut labeler = common::labeler();
+    // the first two voxels share an edge, the last two only a corner
+    let voxels = [(1, 1, 0), (2, 1, 1), (3, 2, 2)];
+    let slices: Vec<_> = (0..3)
+        .map(|z| {
+            image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(5, 4, |x, y| {
+                image::Luma([if voxels.contains(&(x, y, z)) { 255 } else { 0 }])
+            }))
+        })
+        .collect();
+    for (connectivity, components) in [(VolumeConnectivity::Six, 3), (VolumeConnectivity::Eighteen, 2), (VolumeConnectivity::TwentySix, 1)] {
+        let (_, count) = labeler.label_volume(&slices, VolumeOptions { connectivity, ..Default::default() })?;
+        assert_eq!(count, components, "{connectivity:?}-connectivity");
+    }
+    Ok(())
+}